@@ -0,0 +1,101 @@
+use std::fmt;
+
+use crate::types::NumBits;
+
+
+/// How the decimal digits of a binary-coded decimal are laid out.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub enum BcdPacking {
+    /// Two digits per byte, one in each nibble
+    Packed,
+
+    /// One digit per byte, in the low nibble, with the high nibble zero
+    Unpacked,
+}
+
+impl BcdPacking {
+    /// The number of bits used by each digit.
+    pub fn digit_bits(&self) -> NumBits {
+        match self {
+            BcdPacking::Packed => 4,
+            BcdPacking::Unpacked => 8,
+        }
+    }
+}
+
+impl fmt::Display for BcdPacking {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BcdPacking::Packed => write!(f, "bcd"),
+            BcdPacking::Unpacked => write!(f, "ubcd"),
+        }
+    }
+}
+
+/// Convert the raw bits of a binary-coded decimal to its value. The most
+/// significant digit is in the highest bits.
+pub fn from_bcd(bits: u128, num_bits: NumBits, packing: BcdPacking) -> Option<u128> {
+    let digit_bits = packing.digit_bits();
+    let mut value: u128 = 0;
+
+    for digit_index in (0..num_bits / digit_bits).rev() {
+        let digit = (bits >> (digit_index * digit_bits)) & ((1 << digit_bits) - 1);
+
+        if digit > 9 {
+            error!("BCD digit 0x{:X} is not a decimal digit", digit);
+            return None;
+        }
+
+        value = value * 10 + digit;
+    }
+
+    Some(value)
+}
+
+/// Convert a value into the raw bits of a binary-coded decimal, or None if
+/// the value has more digits than fit in the field.
+pub fn to_bcd(mut value: u128, num_bits: NumBits, packing: BcdPacking) -> Option<u128> {
+    let digit_bits = packing.digit_bits();
+    let mut bits: u128 = 0;
+
+    for digit_index in 0..num_bits / digit_bits {
+        bits |= (value % 10) << (digit_index * digit_bits);
+        value /= 10;
+    }
+
+    if value != 0 {
+        return None;
+    }
+
+    Some(bits)
+}
+
+/// The number of decimal digits in a binary-coded decimal field.
+pub fn num_digits(num_bits: NumBits, packing: BcdPacking) -> u32 {
+    (num_bits / packing.digit_bits()) as u32
+}
+
+#[test]
+fn test_bcd_packed() {
+    assert_eq!(from_bcd(0x1234, 16, BcdPacking::Packed), Some(1234));
+    assert_eq!(from_bcd(0x59, 8, BcdPacking::Packed), Some(59));
+    assert_eq!(from_bcd(0x7, 4, BcdPacking::Packed), Some(7));
+    assert_eq!(from_bcd(0x1A, 8, BcdPacking::Packed), None);
+
+    assert_eq!(to_bcd(1234, 16, BcdPacking::Packed), Some(0x1234));
+    assert_eq!(to_bcd(12, 16, BcdPacking::Packed), Some(0x0012));
+    assert_eq!(to_bcd(12345, 16, BcdPacking::Packed), None);
+
+    let max = 10u128.pow(32) - 1;
+    assert_eq!(from_bcd(to_bcd(max, 128, BcdPacking::Packed).unwrap(), 128, BcdPacking::Packed), Some(max));
+}
+
+#[test]
+fn test_bcd_unpacked() {
+    assert_eq!(from_bcd(0x0102, 16, BcdPacking::Unpacked), Some(12));
+    assert_eq!(from_bcd(0x0A, 8, BcdPacking::Unpacked), None);
+    assert_eq!(from_bcd(0x31, 8, BcdPacking::Unpacked), None);
+
+    assert_eq!(to_bcd(12, 16, BcdPacking::Unpacked), Some(0x0102));
+    assert_eq!(to_bcd(123, 16, BcdPacking::Unpacked), None);
+}
@@ -1,20 +1,21 @@
 use std::fmt;
-use std::mem;
 use std::io::Cursor;
 
 use byteorder::{ReadBytesExt, LittleEndian, BigEndian};
 
 use crate::types::*;
+use crate::float::*;
+use crate::bcd::*;
 
 
-const BITS_IN_BUFFER: u8 = 64;
+const BITS_IN_BUFFER: u8 = 128;
 
 /// A bit buffer is a collection of bits that can be pushed to
 /// and pulled from. Care must be taken to use the desired
 /// endianess when interacting with the buffer.
 #[derive(Eq, PartialEq, Debug, Clone, Default)]
 pub struct BitBuffer {
-    pub bits: u64,
+    pub bits: u128,
     pub bits_avail: u8,
 }
 
@@ -45,13 +46,13 @@ impl BitBuffer {
         self.bits_avail == 0
     }
 
-    pub fn mask(&self) -> u64 {
-        2u64.pow(self.bits_avail as u32) - 1
+    pub fn mask(&self) -> u128 {
+        bit_mask(self.bits_avail as NumBits)
     }
 
     pub fn push_byte_be(&mut self, byte: u8) -> Option<()> {
         if self.bits_avail + 8 <= BITS_IN_BUFFER {
-            self.bits = (self.bits << 8) | (byte as u64);
+            self.bits = (self.bits << 8) | (byte as u128);
             self.bits_avail += 8;
             Some(())
         } else {
@@ -61,7 +62,10 @@ impl BitBuffer {
 
     pub fn push_byte_le(&mut self, byte: u8) -> Option<()> {
         if self.bits_avail + 8 <= BITS_IN_BUFFER {
-            self.bits |= (byte as u64) << self.bits_avail as u64;
+            // clear out bits that were already pulled from the buffer
+            self.bits &= self.mask();
+
+            self.bits |= (byte as u128) << self.bits_avail as u128;
             self.bits_avail += 8;
             Some(())
         } else {
@@ -71,79 +75,60 @@ impl BitBuffer {
 
     pub fn push_value(&mut self, value: Value, num_bits: NumBits, endianness: Endianness) -> Option<()> {
         if ((BITS_IN_BUFFER - self.bits_avail) as usize) < num_bits {
-            None
-        } else {
-            let mask = 2u64.pow(num_bits as u32) - 1;
-
-            match endianness {
-                Endianness::Big => {
-                    // make room for the new value
-                    self.bits <<= num_bits;
-
-                    match value {
-                        Value::Uint8(val)  => self.bits |= (val as u64) & mask,
-                        Value::Int8(val)   => self.bits |= (val as u64) & mask,
-                        Value::Uint16(val) => self.bits |= (val as u64) & mask,
-                        Value::Int16(val)  => self.bits |= (val as u64) & mask,
-                        Value::Uint32(val) => self.bits |= (val as u64) & mask,
-                        Value::Int32(val)  => self.bits |= (val as u64) & mask,
-                        Value::Uint64(val) => self.bits |= (val as u64) & mask,
-                        Value::Int64(val)  => self.bits |= (val as u64) & mask,
-                        Value::Float(float_val)  => {
-                            unsafe {
-                                let val = mem::transmute::<&f32, &u32>(&float_val);
-                                self.bits |= (*val as u64) & mask;
-                            }
-                        },
-                        Value::Double(double_val) => {
-                            unsafe {
-                                let val = mem::transmute::<&f64, &u64>(&double_val);
-                                self.bits |= (*val as u64) & mask;
-                            }
-                        },
-                    }
-                },
-
-                Endianness::Little => {
-                    match value {
-                        Value::Uint8(val)  => self.bits |= ((val as u64) & mask) << self.bits_avail,
-                        Value::Int8(val)   => self.bits |= ((val as u64) & mask) << self.bits_avail,
-                        Value::Uint16(val) => self.bits |= ((val as u64) & mask) << self.bits_avail,
-                        Value::Int16(val)  => self.bits |= ((val as u64) & mask) << self.bits_avail,
-                        Value::Uint32(val) => self.bits |= ((val as u64) & mask) << self.bits_avail,
-                        Value::Int32(val)  => self.bits |= ((val as u64) & mask) << self.bits_avail,
-                        Value::Uint64(val) => self.bits |= ((val as u64) & mask) << self.bits_avail,
-                        Value::Int64(val)  => self.bits |= ((val as u64) & mask) << self.bits_avail,
-                        Value::Float(float_val)  => {
-                            unsafe {
-                                let val = mem::transmute::<&f32, &u32>(&float_val);
-                                self.bits |= ((*val as u64) & mask) << self.bits_avail;
-                            }
-                        },
-                        Value::Double(double_val) => {
-                            unsafe {
-                                let val = mem::transmute::<&f64, &u64>(&double_val);
-                                self.bits |= ((*val as u64) & mask) << self.bits_avail;
-                            }
-                        },
-                    }
-                },
-            }
+            return None;
+        }
 
-            self.bits_avail += num_bits as u8;
+        let value_bits = match value {
+            Value::Uint8(val)   => val as u128,
+            Value::Int8(val)    => val as u128,
+            Value::Uint16(val)  => val as u128,
+            Value::Int16(val)   => val as u128,
+            Value::Uint32(val)  => val as u128,
+            Value::Int32(val)   => val as u128,
+            Value::Uint64(val)  => val as u128,
+            Value::Int64(val)   => val as u128,
+            Value::Uint128(val) => val,
+            Value::Int128(val)  => val as u128,
+            Value::Float(val)   => val.to_bits() as u128,
+            Value::Double(val)  => val.to_bits() as u128,
+            Value::Half(val)    => half_to_bits(val as f64) as u128,
+            Value::BFloat16(val) => bfloat16_to_bits(val as f64) as u128,
+            Value::Bool(val)    => val as u128,
+
+            // strings and times are written out as bytes, and are not packed into a bit buffer
+            Value::Str(_) | Value::Time(_) => return None,
+        } & bit_mask(num_bits);
+
+        // clear out bits that were already pulled from the buffer
+        self.bits &= self.mask();
+
+        match endianness {
+            Endianness::Big => {
+                // make room for the new value
+                self.bits = shift_left(self.bits, num_bits) | value_bits;
+            },
 
-            Some(())
+            Endianness::Little => {
+                self.bits |= shift_left(value_bits, self.bits_avail as NumBits);
+            },
         }
+
+        self.bits_avail += num_bits as u8;
+
+        Some(())
     }
 
     pub fn pull_value(&mut self, typ: &FieldType) -> Option<Value> {
+        // strings, varints, and times are read as bytes, and are never pulled from a bit buffer
+        if let FieldType::Str(_, _, _) | FieldType::Varint(_) | FieldType::Time(_, _) | FieldType::Blob(_) | FieldType::Struct = typ {
+            return None;
+        }
+
         let num_bits: u8 = typ.num_bits() as u8;
-        let mask;
-        if num_bits < 64 {
-            mask = (1u64 << num_bits) - 1;
-        } else
-        {
-            mask = (-1i64) as u64;
+        let mask = bit_mask(num_bits as NumBits);
+
+        if num_bits > self.bits_avail {
+            return None;
         }
 
         self.bits_avail -= num_bits;
@@ -152,13 +137,7 @@ impl BitBuffer {
         match typ.endianness() {
             Endianness::Little => {
                 value = self.bits & mask;
-                self.bits = self.bits & !mask;
-
-                // if shifting by 64 bits, self.bits is already cleared,
-                // and this shift would cause an exception in debug builds
-                if num_bits < 64 {
-                    self.bits = self.bits >> num_bits;
-                }
+                self.bits = shift_right(self.bits & !mask, num_bits as NumBits);
             }
             Endianness::Big => {
                 value = (self.bits >> self.bits_avail) & mask;
@@ -168,46 +147,82 @@ impl BitBuffer {
 
         match typ {
             FieldType::Int(_, _, _) => {
+                // sign extend values that are narrower than their type
+                let sign_bit = 1u128 << (num_bits - 1);
+                let value = if value & sign_bit != 0 { value | !mask } else { value };
+
                 if num_bits <= 8 {
                     Some(Value::Int8(value as i8))
                 } else if num_bits <= 16 {
                     Some(Value::Int16(value as i16))
                 } else if num_bits <= 32 {
                     Some(Value::Int32(value as i32))
-                } else if num_bits <= BITS_IN_BUFFER {
+                } else if num_bits <= 64 {
                     Some(Value::Int64(value as i64))
+                } else if num_bits <= BITS_IN_BUFFER {
+                    Some(Value::Int128(value as i128))
                 } else {
                     None
                 }
             }
 
-            FieldType::Uint(_, _, _) => {
+            FieldType::Uint(_, _, _) | FieldType::Pad(_, _, _) | FieldType::Checksum(_, _) => {
                 if num_bits <= 8 {
                     Some(Value::Uint8(value as u8))
                 } else if num_bits <= 16 {
                     Some(Value::Uint16(value as u16))
                 } else if num_bits <= 32 {
                     Some(Value::Uint32(value as u32))
-                } else if num_bits <= BITS_IN_BUFFER {
+                } else if num_bits <= 64 {
                     Some(Value::Uint64(value as u64))
+                } else if num_bits <= BITS_IN_BUFFER {
+                    Some(Value::Uint128(value))
                 } else {
                     None
                 }
             }
 
-            FieldType::Float(_) => {
-                unsafe {
-                    let mut ptr = mem::transmute::<&u64, &f32>(&value);
-                    Some(Value::Float(*ptr))
-                }
-            }
+            FieldType::Float(_) => Some(Value::Float(f32::from_bits(value as u32))),
+
+            FieldType::Double(_) => Some(Value::Double(f64::from_bits(value as u64))),
+
+            FieldType::Half(_) => Some(Value::Half(half_from_bits(value as u16))),
+
+            FieldType::BFloat16(_) => Some(Value::BFloat16(bfloat16_from_bits(value as u16))),
 
-            FieldType::Double(_) => {
-                unsafe {
-                    let mut ptr = mem::transmute::<&u64, &f64>(&value);
-                    Some(Value::Double(*ptr))
+            // decimals are strict, and any digit above 9 is an error
+            FieldType::Bcd(_, _, _, packing) => {
+                from_bcd(value, num_bits as NumBits, *packing).map(Value::Uint128)
+            },
+
+            // booleans are strict, and any value other than 0 or 1 is an error
+            FieldType::Bool(_, _, _) => {
+                match value {
+                    0 => Some(Value::Bool(false)),
+                    1 => Some(Value::Bool(true)),
+                    _ => {
+                        error!("Boolean field has value {}, which is not 0 or 1", value);
+                        None
+                    },
                 }
             }
+
+            FieldType::Str(_, _, _) | FieldType::Varint(_) | FieldType::Time(_, _) | FieldType::Blob(_) | FieldType::Struct => unreachable!(),
+        }
+    }
+
+    /// Pull the lowest byte from the buffer. This is used to write out
+    /// little endian fields, which start with their least significant byte.
+    pub fn pull_byte_le(&mut self) -> Option<u8> {
+        if self.byte_aligned() {
+            let byte = self.bits as u8;
+
+            self.bits >>= 8;
+            self.bits_avail -= 8;
+
+            Some(byte)
+        } else {
+            None
         }
     }
 
@@ -224,6 +239,25 @@ impl BitBuffer {
     }
 }
 
+/// A mask of the given number of low bits.
+fn bit_mask(num_bits: NumBits) -> u128 {
+    if num_bits >= BITS_IN_BUFFER as NumBits {
+        !0
+    } else {
+        (1u128 << num_bits) - 1
+    }
+}
+
+/// Shift left, where shifting out all bits results in zero.
+fn shift_left(bits: u128, num_bits: NumBits) -> u128 {
+    bits.checked_shl(num_bits as u32).unwrap_or(0)
+}
+
+/// Shift right, where shifting out all bits results in zero.
+fn shift_right(bits: u128, num_bits: NumBits) -> u128 {
+    bits.checked_shr(num_bits as u32).unwrap_or(0)
+}
+
 #[test]
 pub fn test_bit_buffer_iter_be() {
     let mut bit_buffer: BitBuffer = Default::default();
@@ -317,13 +351,13 @@ pub fn test_bit_buffer_pull_value_be() {
     bit_buffer.push_value(Value::Int16(2),  4, Endianness::Big);
     bit_buffer.push_value(Value::Uint16(1), 1, Endianness::Big);
 
-    let typ = FieldType::Uint(3, Endianness::Big, BitSize::Bits8);
+    let typ = FieldType::Uint(3, Endianness::Big, BitSize(8));
     assert!(bit_buffer.pull_value(&typ).unwrap() == Value::Uint8(7));
 
-    let typ = FieldType::Int(4, Endianness::Big, BitSize::Bits16);
+    let typ = FieldType::Int(4, Endianness::Big, BitSize(16));
     assert!(bit_buffer.pull_value(&typ).unwrap() == Value::Int8(2));
 
-    let typ = FieldType::Uint(1, Endianness::Big, BitSize::Bits16);
+    let typ = FieldType::Uint(1, Endianness::Big, BitSize(16));
     assert!(bit_buffer.pull_value(&typ).unwrap() == Value::Uint8(1));
 }
 
@@ -335,13 +369,65 @@ pub fn test_bit_buffer_pull_value_le() {
     bit_buffer.push_value(Value::Int16(2),  4, Endianness::Little);
     bit_buffer.push_value(Value::Uint16(1), 1, Endianness::Little);
 
-    let typ = FieldType::Uint(3, Endianness::Little, BitSize::Bits8);
+    let typ = FieldType::Uint(3, Endianness::Little, BitSize(8));
     assert!(bit_buffer.pull_value(&typ).unwrap() == Value::Uint8(7));
 
-    let typ = FieldType::Int(4, Endianness::Little, BitSize::Bits16);
+    let typ = FieldType::Int(4, Endianness::Little, BitSize(16));
     assert!(bit_buffer.pull_value(&typ).unwrap() == Value::Int8(2));
 
-    let typ = FieldType::Uint(1, Endianness::Little, BitSize::Bits16);
+    let typ = FieldType::Uint(1, Endianness::Little, BitSize(16));
     assert!(bit_buffer.pull_value(&typ).unwrap() == Value::Uint8(1));
 }
 
+
+#[test]
+pub fn test_bit_buffer_pull_value_signed() {
+    let mut bit_buffer: BitBuffer = BitBuffer::default();
+
+    bit_buffer.push_value(Value::Int32(-2), 24, Endianness::Big);
+    bit_buffer.push_value(Value::Int8(-1), 4, Endianness::Big);
+    bit_buffer.push_value(Value::Int8(7), 4, Endianness::Big);
+
+    let typ = FieldType::Int(24, Endianness::Big, BitSize(24));
+    assert_eq!(bit_buffer.pull_value(&typ), Some(Value::Int32(-2)));
+
+    let typ = FieldType::Int(4, Endianness::Big, BitSize(8));
+    assert_eq!(bit_buffer.pull_value(&typ), Some(Value::Int8(-1)));
+    assert_eq!(bit_buffer.pull_value(&typ), Some(Value::Int8(7)));
+}
+
+#[test]
+pub fn test_bit_buffer_64_bits() {
+    let mut bit_buffer: BitBuffer = BitBuffer::default();
+
+    bit_buffer.push_value(Value::Uint64(0x0102030405060708), 64, Endianness::Little);
+
+    let bytes: Vec<u8> = (0..8).map(|_| bit_buffer.pull_byte_le().unwrap()).collect();
+    assert_eq!(bytes, vec!(8, 7, 6, 5, 4, 3, 2, 1));
+
+    bit_buffer.push_value(Value::Int64(-1), 64, Endianness::Big);
+    let typ = FieldType::i64(Endianness::Big);
+    assert_eq!(bit_buffer.pull_value(&typ), Some(Value::Int64(-1)));
+}
+
+#[test]
+pub fn test_bit_buffer_128_bits() {
+    let value = 0x0102030405060708090A0B0C0D0E0F10u128;
+
+    let mut bit_buffer: BitBuffer = BitBuffer::default();
+    bit_buffer.push_value(Value::Uint128(value), 128, Endianness::Big);
+    let bytes: Vec<u8> = bit_buffer.clone().collect();
+    assert_eq!(bytes, (1..=16).collect::<Vec<u8>>());
+    assert_eq!(bit_buffer.pull_value(&FieldType::u128(Endianness::Big)), Some(Value::Uint128(value)));
+
+    let mut bit_buffer: BitBuffer = BitBuffer::default();
+    bit_buffer.push_value(Value::Uint128(value), 128, Endianness::Little);
+    let bytes: Vec<u8> = (0..16).map(|_| bit_buffer.pull_byte_le().unwrap()).collect();
+    assert_eq!(bytes, (1..=16).rev().collect::<Vec<u8>>());
+
+    let mut bit_buffer: BitBuffer = BitBuffer::default();
+    bit_buffer.push_value(Value::Int128(-3), 96, Endianness::Little);
+    bit_buffer.push_value(Value::Uint32(5), 32, Endianness::Little);
+    assert_eq!(bit_buffer.pull_value(&FieldType::Int(96, Endianness::Little, BitSize(128))), Some(Value::Int128(-3)));
+    assert_eq!(bit_buffer.pull_value(&FieldType::Uint(32, Endianness::Little, BitSize(128))), Some(Value::Uint32(5)));
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+
+use crate::types::TextEncoding;
+
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How the bytes of a variable length blob are written in a csv file.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub enum BlobFormat {
+    /// Two hex digits per byte
+    Hex,
+
+    /// Standard base64, with padding
+    Base64,
+
+    /// Text in the given character encoding
+    Text(TextEncoding),
+}
+
+impl BlobFormat {
+    /// Format the bytes of a blob as text.
+    pub fn format(&self, bytes: &[u8]) -> String {
+        match self {
+            BlobFormat::Hex => bytes.iter().map(|byte| format!("{:02X}", byte)).collect(),
+
+            BlobFormat::Base64 => to_base64(bytes),
+
+            BlobFormat::Text(encoding) => encoding.decode(bytes),
+        }
+    }
+
+    /// Parse the text of a blob back into its bytes, or None if the text is not valid.
+    pub fn parse(&self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            BlobFormat::Hex => from_hex(text),
+
+            BlobFormat::Base64 => from_base64(text),
+
+            BlobFormat::Text(encoding) => encoding.encode(text),
+        }
+    }
+}
+
+impl fmt::Display for BlobFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlobFormat::Hex => write!(f, "hex"),
+            BlobFormat::Base64 => write!(f, "base64"),
+            BlobFormat::Text(TextEncoding::Ascii) => write!(f, "text"),
+            BlobFormat::Text(encoding) => write!(f, "text_{}", encoding),
+        }
+    }
+}
+
+/// Parse hex digits into bytes, ignoring whitespace between them.
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|byte| !byte.is_ascii_whitespace()).collect();
+
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+
+    digits.chunks(2)
+          .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+          .collect()
+}
+
+fn to_base64(bytes: &[u8]) -> String {
+    let mut text = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| bits | (*byte as u32) << (16 - 8 * index));
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(BASE64_ALPHABET[((bits >> (18 - 6 * index)) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+
+    text
+}
+
+/// Parse base64 into bytes, ignoring whitespace. Padding is optional.
+fn from_base64(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text.bytes().filter(|byte| !byte.is_ascii_whitespace()).collect();
+    let digits = match digits.iter().position(|byte| *byte == b'=') {
+        // only padding may follow the first '='
+        Some(index) if digits[index..].iter().all(|byte| *byte == b'=') && digits.len().is_multiple_of(4) => &digits[..index],
+        Some(_) => return None,
+        None => &digits[..],
+    };
+
+    if digits.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut bytes = vec!();
+
+    for chunk in digits.chunks(4) {
+        let mut bits: u32 = 0;
+
+        for (index, digit) in chunk.iter().enumerate() {
+            let value = BASE64_ALPHABET.iter().position(|chr| chr == digit)? as u32;
+            bits |= value << (18 - 6 * index);
+        }
+
+        for index in 0..chunk.len() - 1 {
+            bytes.push((bits >> (16 - 8 * index)) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+#[test]
+fn test_blob_hex() {
+    assert_eq!(BlobFormat::Hex.format(&[0x01, 0xAB, 0xFF]), "01ABFF");
+    assert_eq!(BlobFormat::Hex.format(&[]), "");
+
+    assert_eq!(BlobFormat::Hex.parse("01abFF"), Some(vec!(0x01, 0xAB, 0xFF)));
+    assert_eq!(BlobFormat::Hex.parse("01 AB FF"), Some(vec!(0x01, 0xAB, 0xFF)));
+    assert_eq!(BlobFormat::Hex.parse("1AB"), None);
+    assert_eq!(BlobFormat::Hex.parse("0G"), None);
+}
+
+#[test]
+fn test_blob_base64() {
+    assert_eq!(BlobFormat::Base64.format(b""), "");
+    assert_eq!(BlobFormat::Base64.format(b"f"), "Zg==");
+    assert_eq!(BlobFormat::Base64.format(b"fo"), "Zm8=");
+    assert_eq!(BlobFormat::Base64.format(b"foo"), "Zm9v");
+    assert_eq!(BlobFormat::Base64.format(&[0xFB, 0xFF]), "+/8=");
+
+    assert_eq!(BlobFormat::Base64.parse("Zm9vYg=="), Some(b"foob".to_vec()));
+    assert_eq!(BlobFormat::Base64.parse("Zm9vYg"), Some(b"foob".to_vec()));
+    assert_eq!(BlobFormat::Base64.parse("+/8="), Some(vec!(0xFB, 0xFF)));
+    assert_eq!(BlobFormat::Base64.parse("Zm9vY"), None);
+    assert_eq!(BlobFormat::Base64.parse("Zg=a"), None);
+    assert_eq!(BlobFormat::Base64.parse("Z!=="), None);
+}
+
+#[test]
+fn test_blob_text() {
+    let format = BlobFormat::Text(TextEncoding::Latin1);
+    assert_eq!(format.format(b"caf\xe9"), "caf\u{e9}");
+    assert_eq!(format.parse("caf\u{e9}"), Some(b"caf\xe9".to_vec()));
+    assert_eq!(format.to_string(), "text_latin1");
+    assert_eq!(BlobFormat::Text(TextEncoding::Ascii).parse("caf\u{e9}"), None);
+}
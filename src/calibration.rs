@@ -0,0 +1,229 @@
+use crate::types::*;
+use crate::bcd::num_digits;
+
+
+/// A calibration converts the raw value of a field into engineering units.
+///
+/// The conversion is a polynomial, with its coefficients given in increasing
+/// order of power, so a scale and offset is the polynomial 'offset + scale * x'.
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+pub struct Calibration {
+    pub coefficients: Vec<f64>,
+}
+
+impl Calibration {
+    pub fn linear(scale: f64, offset: f64) -> Calibration {
+        Calibration { coefficients: vec!(offset, scale) }
+    }
+
+    /// Parse a polynomial given as coefficients separated by semicolons,
+    /// starting with the constant term, such as '1.5;0.25;0.001'.
+    pub fn parse_polynomial(text: &str) -> Option<Calibration> {
+        let mut coefficients = vec!();
+
+        for coefficient in text.split(';').map(|coefficient| coefficient.trim()) {
+            match coefficient.parse::<f64>() {
+                Ok(coefficient) if coefficient.is_finite() => coefficients.push(coefficient),
+
+                _ => {
+                    error!("Polynomial coefficient '{}' is not a number", coefficient);
+                    return None;
+                },
+            }
+        }
+
+        Some(Calibration { coefficients })
+    }
+
+    /// Convert a raw value into engineering units.
+    pub fn convert(&self, raw: f64) -> f64 {
+        self.coefficients.iter().rev().fold(0.0, |result, coefficient| result * raw + coefficient)
+    }
+
+    fn derivative(&self, raw: f64) -> f64 {
+        self.coefficients.iter()
+                         .enumerate()
+                         .skip(1)
+                         .rev()
+                         .fold(0.0, |result, (power, coefficient)| result * raw + coefficient * power as f64)
+    }
+
+    /// Find the raw value that converts to the given engineering value, searching
+    /// between the smallest and largest raw values of the field.
+    ///
+    /// Polynomials are solved by bisection when the value lies between the conversion
+    /// of the smallest and largest raw values, and otherwise by Newton's method.
+    pub fn invert(&self, value: f64, min: f64, max: f64) -> Option<f64> {
+        let coefficients = &self.coefficients;
+
+        if coefficients.len() < 2 || coefficients[1..].iter().all(|coefficient| *coefficient == 0.0) {
+            error!("A calibration with no dependence on the raw value can not be inverted");
+            return None;
+        }
+
+        if coefficients.len() == 2 {
+            return Some((value - coefficients[0]) / coefficients[1]);
+        }
+
+        let at_min = self.convert(min) - value;
+        let at_max = self.convert(max) - value;
+
+        if at_min == 0.0 {
+            return Some(min);
+        } else if at_max == 0.0 {
+            return Some(max);
+        }
+
+        if at_min.signum() != at_max.signum() && !at_min.is_nan() && !at_max.is_nan() {
+            let (mut low, mut high) = (min, max);
+
+            // bisect until the interval can not be split any further
+            for _ in 0..4096 {
+                let mid = low + (high - low) / 2.0;
+
+                if mid == low || mid == high {
+                    break;
+                }
+
+                let at_mid = self.convert(mid) - value;
+
+                if at_mid == 0.0 {
+                    return Some(mid);
+                } else if at_mid.signum() == at_min.signum() {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+
+            return Some(low + (high - low) / 2.0);
+        }
+
+        // start from the inverse of the linear part of the polynomial
+        let mut raw = if coefficients[1] != 0.0 { (value - coefficients[0]) / coefficients[1] } else { 0.0 };
+
+        for _ in 0..100 {
+            let error = self.convert(raw) - value;
+
+            if error.abs() <= 1e-9 * value.abs().max(1.0) {
+                return Some(raw);
+            }
+
+            let slope = self.derivative(raw);
+            if slope == 0.0 || !slope.is_finite() {
+                break;
+            }
+
+            raw -= error / slope;
+        }
+
+        error!("Could not find a raw value for {}", value);
+        None
+    }
+}
+
+/// The smallest and largest raw values a field can hold.
+pub fn raw_range(typ: FieldType) -> (f64, f64) {
+    match typ {
+        FieldType::Int(num_bits, _, _) => {
+            let limit = 2f64.powi(num_bits as i32 - 1);
+            (-limit, limit - 1.0)
+        },
+
+        FieldType::Uint(num_bits, _, _) => (0.0, 2f64.powi(num_bits as i32) - 1.0),
+
+        FieldType::Bcd(num_bits, _, _, packing) => (0.0, 10f64.powi(num_digits(num_bits, packing) as i32) - 1.0),
+
+        FieldType::Float(_) => (f32::MIN as f64, f32::MAX as f64),
+
+        FieldType::Half(_) => (-65504.0, 65504.0),
+
+        FieldType::BFloat16(_) => (-3.3895314e38, 3.3895314e38),
+
+        _ => (f64::MIN, f64::MAX),
+    }
+}
+
+/// Convert an engineering value back to the text of a raw value for the given
+/// field type, checking that the raw value fits in the field.
+pub fn uncalibrate(calibration: &Calibration, typ: FieldType, value_str: &str) -> Option<String> {
+    let value = match value_str.trim().parse::<f64>() {
+        Ok(value) => value,
+
+        Err(_) => {
+            error!("'{}' is not a number", value_str.trim());
+            return None;
+        },
+    };
+
+    let (min, max) = raw_range(typ);
+    let raw = calibration.invert(value, min, max)?;
+
+    match typ {
+        FieldType::Int(_, _, _) | FieldType::Uint(_, _, _) | FieldType::Bcd(_, _, _, _) => {
+            let raw = raw.round();
+
+            if !(min..=max).contains(&raw) {
+                error!("Value {} gives raw value {}, which does not fit in a {} field", value, raw, typ);
+                return None;
+            }
+
+            match typ {
+                FieldType::Int(_, _, _) => Some(format!("{}", raw as i128)),
+                _ => Some(format!("{}", raw as u128)),
+            }
+        },
+
+        _ => {
+            if !(min..=max).contains(&raw) {
+                error!("Value {} gives raw value {}, which does not fit in a {} field", value, raw, typ);
+                return None;
+            }
+
+            Some(format!("{}", raw))
+        },
+    }
+}
+
+#[test]
+fn test_calibration_convert() {
+    let calibration = Calibration::linear(0.5, -10.0);
+    assert_eq!(calibration.convert(4.0), -8.0);
+    assert_eq!(calibration.invert(-8.0, 0.0, 255.0), Some(4.0));
+
+    let calibration = Calibration::parse_polynomial("1; 2; 3").unwrap();
+    assert_eq!(calibration.coefficients, vec!(1.0, 2.0, 3.0));
+    assert_eq!(calibration.convert(2.0), 17.0);
+    assert_eq!(calibration.derivative(2.0), 14.0);
+
+    assert!(Calibration::parse_polynomial("1;two").is_none());
+    assert!(Calibration::parse_polynomial("").is_none());
+}
+
+#[test]
+fn test_calibration_invert_polynomial() {
+    let calibration = Calibration::parse_polynomial("1;2;3").unwrap();
+
+    // bisection within the raw range
+    let raw = calibration.invert(17.0, 0.0, 255.0).unwrap();
+    assert!((raw - 2.0).abs() < 1e-9);
+
+    // newton's method outside of it
+    let raw = calibration.invert(17.0, 10.0, 255.0).unwrap();
+    assert!((raw - 2.0).abs() < 1e-9);
+
+    assert!(Calibration::parse_polynomial("5;0").unwrap().invert(5.0, 0.0, 255.0).is_none());
+}
+
+#[test]
+fn test_uncalibrate() {
+    let calibration = Calibration::linear(0.1, 0.0);
+
+    assert_eq!(uncalibrate(&calibration, FieldType::u8(Endianness::Big), "3.3"), Some("33".to_string()));
+    assert_eq!(uncalibrate(&calibration, FieldType::u8(Endianness::Big), "25.6"), None);
+    assert_eq!(uncalibrate(&calibration, FieldType::u8(Endianness::Big), "-0.1"), None);
+    assert_eq!(uncalibrate(&calibration, FieldType::i8(Endianness::Big), "-12.8"), Some("-128".to_string()));
+    assert_eq!(uncalibrate(&calibration, FieldType::Uint(4, Endianness::Big, BitSize(8)), "1.6"), None);
+    assert_eq!(uncalibrate(&calibration, FieldType::double(Endianness::Big), "1.5"), Some("15".to_string()));
+    assert_eq!(uncalibrate(&calibration, FieldType::u8(Endianness::Big), "volts"), None);
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::types::NumBits;
+use crate::template::parse_integer;
+
+
+/// The algorithm used to compute a checksum field over the bytes of a record.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub enum ChecksumKind {
+    /// CRC-16-CCITT, with polynomial 0x1021 and initial value 0xFFFF, as used by CCSDS
+    Crc16Ccitt,
+
+    /// CRC-32, as used by Ethernet and zip
+    Crc32,
+
+    /// Fletcher-16, the sums of the bytes and of those sums, modulo 255
+    Fletcher16,
+
+    /// Sum of the bytes, modulo 256
+    Sum8,
+
+    /// Sum of the bytes, modulo 65536
+    Sum16,
+
+    /// Exclusive or of the bytes
+    Xor8,
+}
+
+impl ChecksumKind {
+    pub fn num_bits(&self) -> NumBits {
+        match self {
+            ChecksumKind::Crc16Ccitt => 16,
+            ChecksumKind::Crc32 => 32,
+            ChecksumKind::Fletcher16 => 16,
+            ChecksumKind::Sum8 => 8,
+            ChecksumKind::Sum16 => 16,
+            ChecksumKind::Xor8 => 8,
+        }
+    }
+
+    /// Compute the checksum of the given bytes.
+    pub fn compute(&self, bytes: &[u8]) -> u128 {
+        match self {
+            ChecksumKind::Crc16Ccitt => {
+                let crc = bytes.iter().fold(0xFFFFu16, |crc, byte| {
+                    (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+                        if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 }
+                    })
+                });
+
+                crc as u128
+            },
+
+            ChecksumKind::Crc32 => {
+                let crc = bytes.iter().fold(0xFFFFFFFFu32, |crc, byte| {
+                    (0..8).fold(crc ^ (*byte as u32), |crc, _| {
+                        if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 }
+                    })
+                });
+
+                (crc ^ 0xFFFFFFFF) as u128
+            },
+
+            ChecksumKind::Fletcher16 => {
+                let (sum1, sum2) = bytes.iter().fold((0u16, 0u16), |(sum1, sum2), byte| {
+                    let sum1 = (sum1 + *byte as u16) % 255;
+                    (sum1, (sum2 + sum1) % 255)
+                });
+
+                ((sum2 << 8) | sum1) as u128
+            },
+
+            ChecksumKind::Sum8 => bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) as u128,
+
+            ChecksumKind::Sum16 => bytes.iter().fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16)) as u128,
+
+            ChecksumKind::Xor8 => bytes.iter().fold(0u8, |sum, byte| sum ^ byte) as u128,
+        }
+    }
+}
+
+impl fmt::Display for ChecksumKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksumKind::Crc16Ccitt => write!(f, "crc16_ccitt"),
+            ChecksumKind::Crc32 => write!(f, "crc32"),
+            ChecksumKind::Fletcher16 => write!(f, "fletcher16"),
+            ChecksumKind::Sum8 => write!(f, "sum8"),
+            ChecksumKind::Sum16 => write!(f, "sum16"),
+            ChecksumKind::Xor8 => write!(f, "xor8"),
+        }
+    }
+}
+
+/// The bytes of a record covered by a checksum, given either as a range of
+/// byte offsets within the record, such as '0..6', or as a range of fields,
+/// such as 'version..data'. A byte range without an end, such as '6..', ends
+/// at the checksum itself.
+#[derive(Eq, PartialEq, Debug, Clone, Deserialize)]
+pub enum Span {
+    Bytes(usize, Option<usize>),
+    Fields(String, String),
+}
+
+impl Span {
+    pub fn parse(text: &str) -> Option<Span> {
+        let text = text.trim();
+
+        let (first, last) = match text.find("..") {
+            Some(index) => (text[..index].trim(), text[index + 2..].trim()),
+            None => (text, text),
+        };
+
+        let offset = |text: &str| parse_integer(text).map(|offset| offset as usize);
+
+        match (offset(first), offset(last), last.is_empty()) {
+            (Some(start), _, true) => Some(Span::Bytes(start, None)),
+
+            (Some(start), Some(end), false) if start <= end => Some(Span::Bytes(start, Some(end))),
+
+            (None, None, false) if !first.is_empty() => Some(Span::Fields(first.to_string(), last.to_string())),
+
+            _ => {
+                error!("Could not parse span '{}', which should be a range of bytes or fields such as '0..6' or 'version..data'", text);
+                None
+            },
+        }
+    }
+
+    /// The range of bytes within a record that this span covers, given where
+    /// each field of the record was placed and where the checksum itself is.
+    pub fn range(&self, fields: &[(String, Range<usize>)], checksum: &Range<usize>) -> Option<Range<usize>> {
+        match self {
+            Span::Bytes(start, end) => Some(*start..end.unwrap_or(checksum.start)),
+
+            Span::Fields(first, last) => {
+                // a name covers the field itself, along with every element or field within it
+                let named = |name: &String, description: &String| {
+                    description == name ||
+                    description.strip_prefix(name.as_str()).map(|rest| rest.starts_with('.') || rest.starts_with('[')).unwrap_or(false)
+                };
+
+                let start = fields.iter().find(|(description, _)| named(first, description)).map(|(_, range)| range.start);
+                let end = fields.iter().rev().find(|(description, _)| named(last, description)).map(|(_, range)| range.end);
+
+                match (start, end) {
+                    (Some(start), Some(end)) if start <= end => Some(start..end),
+
+                    _ => {
+                        error!("Span {}..{} does not give a range of fields in the record", first, last);
+                        None
+                    },
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Span::Bytes(start, Some(end)) => write!(f, "{}..{}", start, end),
+            Span::Bytes(start, None) => write!(f, "{}..", start),
+            Span::Fields(first, last) if first == last => write!(f, "{}", first),
+            Span::Fields(first, last) => write!(f, "{}..{}", first, last),
+        }
+    }
+}
+
+/// Compute a checksum over the bytes of a record given by its span, or over every
+/// byte before it without a span. Any bytes of the checksum itself within the
+/// span are counted as zeros.
+pub fn record_checksum(kind: ChecksumKind,
+                       span: &Option<Span>,
+                       record: &[u8],
+                       fields: &[(String, Range<usize>)],
+                       checksum: &Range<usize>) -> Option<u128> {
+    let range = match span {
+        Some(span) => span.range(fields, checksum)?,
+        None => 0..checksum.start,
+    };
+
+    if range.start > range.end || range.end > record.len() {
+        error!("Checksum span covers bytes {}..{}, which are not within the record of {} bytes", range.start, range.end, record.len());
+        return None;
+    }
+
+    let mut bytes = record[range.clone()].to_vec();
+
+    for offset in checksum.start.max(range.start)..checksum.end.min(range.end) {
+        bytes[offset - range.start] = 0;
+    }
+
+    Some(kind.compute(&bytes))
+}
+
+/// The type of the row following a checksum's row in row mode, flagging whether
+/// it is valid. This is reserved, and is not a field type.
+pub const VALID_ROW_TYPE: &str = "valid";
+
+/// The description of the column, or row, flagging whether a decoded checksum is valid.
+pub fn valid_header(description: &str) -> String {
+    format!("{} valid", description)
+}
+
+#[test]
+fn test_checksum_compute() {
+    let check = b"123456789";
+
+    assert_eq!(ChecksumKind::Crc16Ccitt.compute(check), 0x29B1);
+    assert_eq!(ChecksumKind::Crc32.compute(check), 0xCBF43926);
+    assert_eq!(ChecksumKind::Fletcher16.compute(b"abcde"), 0xC8F0);
+    assert_eq!(ChecksumKind::Sum8.compute(&[0xFF, 0x02]), 0x01);
+    assert_eq!(ChecksumKind::Sum16.compute(&[0xFF, 0x02]), 0x101);
+    assert_eq!(ChecksumKind::Xor8.compute(&[0x0F, 0xFF]), 0xF0);
+
+    assert_eq!(ChecksumKind::Crc32.compute(&[]), 0);
+    assert_eq!(ChecksumKind::Crc16Ccitt.to_string(), "crc16_ccitt");
+}
+
+#[test]
+fn test_checksum_span() {
+    assert_eq!(Span::parse("0..6"), Some(Span::Bytes(0, Some(6))));
+    assert_eq!(Span::parse(" 2 .. "), Some(Span::Bytes(2, None)));
+    assert_eq!(Span::parse("version..data"), Some(Span::Fields("version".to_string(), "data".to_string())));
+    assert_eq!(Span::parse("header"), Some(Span::Fields("header".to_string(), "header".to_string())));
+    assert_eq!(Span::parse("6..2"), None);
+    assert_eq!(Span::parse("..4"), None);
+    assert_eq!(Span::parse("header..4"), None);
+
+    let fields = vec!(("header.version".to_string(), 0..1),
+                      ("header.length".to_string(), 1..3),
+                      ("data[0]".to_string(), 3..4),
+                      ("data[1]".to_string(), 4..5),
+                      ("crc".to_string(), 5..7));
+    let crc = 5..7;
+
+    assert_eq!(Span::parse("header").unwrap().range(&fields, &crc), Some(0..3));
+    assert_eq!(Span::parse("header.length..data").unwrap().range(&fields, &crc), Some(1..5));
+    assert_eq!(Span::parse("1..").unwrap().range(&fields, &crc), Some(1..5));
+    assert_eq!(Span::parse("dat").unwrap().range(&fields, &crc), None);
+
+    // the checksum's own bytes are counted as zeros
+    let record = [1, 2, 3, 4, 5, 0xFF, 0xFF];
+    assert_eq!(record_checksum(ChecksumKind::Sum8, &None, &record, &fields, &crc), Some(15));
+    assert_eq!(record_checksum(ChecksumKind::Sum8, &Span::parse("0..7"), &record, &fields, &crc), Some(15));
+    assert_eq!(record_checksum(ChecksumKind::Sum8, &Span::parse("0..8"), &record, &fields, &crc), None);
+}
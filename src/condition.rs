@@ -0,0 +1,345 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::types::*;
+use crate::template::parse_integer;
+
+
+/// A condition on the values of earlier fields of a record, such as
+/// 'secHeaderFlag == 1', deciding whether a field is present.
+///
+/// Comparisons are made with '==', '!=', '<', '<=', '>', and '>=', and can be
+/// combined with '&&', '||', '!', and parentheses. A field on its own is true
+/// when it is not zero.
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+pub struct Condition {
+    text: String,
+    expression: Expression,
+}
+
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Operand, Comparison, Operand),
+    Truthy(Operand),
+}
+
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+enum Operand {
+    Field(String),
+    Literal(Value),
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+enum Token {
+    Operand(Operand),
+    Comparison(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Option<Condition> {
+        let tokens = tokenize(text)?;
+        let mut position = 0;
+
+        let expression = parse_or(&tokens, &mut position);
+
+        match expression {
+            Some(expression) if position == tokens.len() => {
+                Some(Condition { text: text.trim().to_string(), expression })
+            },
+
+            _ => {
+                error!("Could not parse condition '{}'", text.trim());
+                None
+            },
+        }
+    }
+
+    /// Evaluate the condition, looking up the value of each field it refers to.
+    /// Fields that can not be found are an error.
+    pub fn evaluate<'v, F>(&self, lookup: &F) -> Option<bool>
+        where F: Fn(&str) -> Option<&'v Value> {
+        evaluate(&self.expression, lookup)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec!();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let rest: String = chars[index..].iter().take(2).collect();
+
+        let (token, length) = match rest.as_str() {
+            "==" => (Token::Comparison(Comparison::Equal), 2),
+            "!=" => (Token::Comparison(Comparison::NotEqual), 2),
+            "<=" => (Token::Comparison(Comparison::LessEqual), 2),
+            ">=" => (Token::Comparison(Comparison::GreaterEqual), 2),
+            "&&" => (Token::And, 2),
+            "||" => (Token::Or, 2),
+
+            _ => match chars[index] {
+                chr if chr.is_whitespace() => {
+                    index += 1;
+                    continue;
+                },
+
+                '<' => (Token::Comparison(Comparison::Less), 1),
+                '>' => (Token::Comparison(Comparison::Greater), 1),
+                '!' => (Token::Not, 1),
+                '(' => (Token::Open, 1),
+                ')' => (Token::Close, 1),
+
+                // quoted text is a string
+                quote @ '\'' | quote @ '"' => {
+                    let length = chars[index + 1..].iter().position(|chr| *chr == quote)?;
+                    let text: String = chars[index + 1..index + 1 + length].iter().collect();
+                    (Token::Operand(Operand::Literal(Value::Str(text))), length + 2)
+                },
+
+                // anything else runs up to the next operator, and is either a
+                // number or the description of a field, which may contain spaces
+                _ => {
+                    let length = chars[index..].iter()
+                                               .position(|chr| "=!<>&|()".contains(*chr))
+                                               .unwrap_or(chars.len() - index);
+                    let word: String = chars[index..index + length].iter().collect();
+                    (Token::Operand(parse_operand(word.trim())), length)
+                },
+            },
+        };
+
+        tokens.push(token);
+        index += length;
+    }
+
+    Some(tokens)
+}
+
+fn parse_operand(word: &str) -> Operand {
+    let negative = word.starts_with('-');
+    let digits = word.trim_start_matches('-');
+
+    if let Some(number) = parse_integer(digits).and_then(|number| i128::try_from(number).ok()) {
+        return Operand::Literal(Value::Int128(if negative { -number } else { number }));
+    }
+
+    match word {
+        "true" => Operand::Literal(Value::Int128(1)),
+        "false" => Operand::Literal(Value::Int128(0)),
+
+        _ => match word.parse::<f64>() {
+            Ok(number) if number.is_finite() => Operand::Literal(Value::Double(number)),
+            _ => Operand::Field(word.to_string()),
+        },
+    }
+}
+
+fn parse_or(tokens: &[Token], position: &mut usize) -> Option<Expression> {
+    let mut expression = parse_and(tokens, position)?;
+
+    while tokens.get(*position) == Some(&Token::Or) {
+        *position += 1;
+        expression = Expression::Or(Box::new(expression), Box::new(parse_and(tokens, position)?));
+    }
+
+    Some(expression)
+}
+
+fn parse_and(tokens: &[Token], position: &mut usize) -> Option<Expression> {
+    let mut expression = parse_not(tokens, position)?;
+
+    while tokens.get(*position) == Some(&Token::And) {
+        *position += 1;
+        expression = Expression::And(Box::new(expression), Box::new(parse_not(tokens, position)?));
+    }
+
+    Some(expression)
+}
+
+fn parse_not(tokens: &[Token], position: &mut usize) -> Option<Expression> {
+    match tokens.get(*position)? {
+        Token::Not => {
+            *position += 1;
+            Some(Expression::Not(Box::new(parse_not(tokens, position)?)))
+        },
+
+        Token::Open => {
+            *position += 1;
+            let expression = parse_or(tokens, position)?;
+
+            if tokens.get(*position) != Some(&Token::Close) {
+                return None;
+            }
+
+            *position += 1;
+            Some(expression)
+        },
+
+        Token::Operand(left) => {
+            *position += 1;
+
+            match tokens.get(*position) {
+                Some(Token::Comparison(comparison)) => {
+                    match tokens.get(*position + 1)? {
+                        Token::Operand(right) => {
+                            *position += 2;
+                            Some(Expression::Compare(left.clone(), *comparison, right.clone()))
+                        },
+
+                        _ => None,
+                    }
+                },
+
+                _ => Some(Expression::Truthy(left.clone())),
+            }
+        },
+
+        _ => None,
+    }
+}
+
+fn evaluate<'v, F>(expression: &Expression, lookup: &F) -> Option<bool>
+    where F: Fn(&str) -> Option<&'v Value> {
+
+    match expression {
+        Expression::Or(left, right) => Some(evaluate(left, lookup)? || evaluate(right, lookup)?),
+
+        Expression::And(left, right) => Some(evaluate(left, lookup)? && evaluate(right, lookup)?),
+
+        Expression::Not(expression) => Some(!evaluate(expression, lookup)?),
+
+        Expression::Truthy(operand) => {
+            let value = operand_value(operand, lookup)?;
+            Some(compare(&value, &Value::Int128(0)) != Some(Ordering::Equal))
+        },
+
+        Expression::Compare(left, comparison, right) => {
+            let ordering = compare(&operand_value(left, lookup)?, &operand_value(right, lookup)?);
+
+            Some(match comparison {
+                Comparison::Equal => ordering == Some(Ordering::Equal),
+                Comparison::NotEqual => ordering != Some(Ordering::Equal),
+                Comparison::Less => ordering == Some(Ordering::Less),
+                Comparison::LessEqual => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+                Comparison::Greater => ordering == Some(Ordering::Greater),
+                Comparison::GreaterEqual => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+            })
+        },
+    }
+}
+
+/// The value of an operand, with booleans treated as the numbers 0 and 1.
+fn operand_value<'v, F>(operand: &Operand, lookup: &F) -> Option<Value>
+    where F: Fn(&str) -> Option<&'v Value> {
+
+    let value = match operand {
+        Operand::Literal(value) => value.clone(),
+
+        Operand::Field(name) => {
+            match lookup(name) {
+                Some(value) => value.clone(),
+
+                None => {
+                    error!("Condition refers to field '{}', which has not been read", name);
+                    return None;
+                },
+            }
+        },
+    };
+
+    match value {
+        Value::Bool(flag) => Some(Value::Int128(flag as i128)),
+        value => Some(value),
+    }
+}
+
+/// Compare two values, as integers if they both are, otherwise as numbers or text.
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    if let (Some(left), Some(right)) = (left.as_integer(), right.as_integer()) {
+        return Some(left.cmp(&right));
+    }
+
+    match (left.as_float(), right.as_float()) {
+        (Some(left), Some(right)) => left.partial_cmp(&right),
+        (None, None) => Some(left.to_string().cmp(&right.to_string())),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_condition_compare() {
+    let flag = Value::Uint8(1);
+    let mode = Value::Str("SAFE".to_string());
+    let lookup = |name: &str| match name {
+        "secHeaderFlag" => Some(&flag),
+        "header.mode name" => Some(&mode),
+        _ => None,
+    };
+
+    let check = |text: &str| Condition::parse(text).unwrap().evaluate(&lookup);
+
+    assert_eq!(check("secHeaderFlag == 1"), Some(true));
+    assert_eq!(check("secHeaderFlag != 1"), Some(false));
+    assert_eq!(check("secHeaderFlag>=0x01"), Some(true));
+    assert_eq!(check("secHeaderFlag < 0.5"), Some(false));
+    assert_eq!(check("secHeaderFlag > -1"), Some(true));
+    assert_eq!(check("secHeaderFlag"), Some(true));
+    assert_eq!(check("header.mode name == 'SAFE'"), Some(true));
+    assert_eq!(check("header.mode name == 1"), Some(false));
+
+    // fields that have not been read are an error
+    assert_eq!(check("missing == 1"), None);
+}
+
+#[test]
+fn test_condition_logic() {
+    let one = Value::Int32(1);
+    let flag = Value::Bool(false);
+    let lookup = |name: &str| match name {
+        "a" => Some(&one),
+        "b" => Some(&flag),
+        _ => None,
+    };
+
+    let check = |text: &str| Condition::parse(text).unwrap().evaluate(&lookup);
+
+    assert_eq!(check("a == 1 && b"), Some(false));
+    assert_eq!(check("a == 1 && !b"), Some(true));
+    assert_eq!(check("b || a == 1 && b == false"), Some(true));
+    assert_eq!(check("(b || a == 1) && b == true"), Some(false));
+    assert_eq!(check("!(a == 2)"), Some(true));
+
+    assert!(Condition::parse("a ==").is_none());
+    assert!(Condition::parse("(a == 1").is_none());
+    assert!(Condition::parse("a == 1)").is_none());
+    assert!(Condition::parse("'unterminated").is_none());
+    assert!(Condition::parse("").is_none());
+
+    assert_eq!(Condition::parse(" a == 1 ").unwrap().to_string(), "a == 1");
+}
@@ -1,5 +1,9 @@
 use std::fs::File;
-use std::io::{Write, Read, Cursor, BufReader};
+use std::io::{self, Write, Read, BufReader};
+use std::collections::HashMap;
+use std::ops::Range;
+#[cfg(test)]
+use std::io::Cursor;
 
 use byteorder::ReadBytesExt;
 
@@ -7,60 +11,443 @@ use byteorder::ReadBytesExt;
 use assert_approx_eq::assert_approx_eq;
 
 use crate::types::*;
+use crate::options::{Framing, PartialRecord};
 use crate::template::*;
+use crate::layout::*;
+use crate::enumeration::*;
+#[cfg(test)]
+use crate::calibration::*;
 use crate::bit_buffer::*;
+use crate::reader::*;
+use crate::varint;
+use crate::checksum::{record_checksum, valid_header, VALID_ROW_TYPE};
+#[cfg(test)]
+use crate::checksum::Span;
+#[cfg(test)]
+use crate::condition::Condition;
+#[cfg(test)]
+use crate::encode::encode;
 
 
-pub fn decode<R: Read, W: Write>(input: &mut R, output_file: &mut W, templates: &Vec<Template>, rows: bool) -> Option<()> {
-    let template_bytes = templates.num_bits() / 8;
-    let mut cursor = Cursor::new(vec![0; template_bytes]);
+/// How the input ended, either after a whole record, or within a record
+/// starting at the given byte offset, leaving the given bytes.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Ending {
+    Complete,
+    Partial(u64, Vec<u8>),
+}
+
+pub fn decode<R: Read, W: Write>(input: &mut R,
+                                 output_file: &mut W,
+                                 templates: &[Template],
+                                 rows: bool,
+                                 values_mode: ValuesMode,
+                                 framing: &Framing,
+                                 partial: &PartialRecord) -> Option<Ending> {
+    // fields are read directly from the input, as varints are only
+    // known to be complete once they have been read
+    let mut input = InputReader::new(input);
+
+    // arrays are decoded as a column for each element, and fields repeated
+    // by a count field as a single column
+    let columns: Vec<Element> = record_columns(templates).into_iter()
+                                                         .filter(|element| !element.template.is_hidden())
+                                                         .collect();
+
+    // when writing both raw and converted values, converted fields get a second column,
+    // and checksums are followed by a column flagging whether they are valid
+    let mut headers: Vec<String> = vec!();
+    let mut column_indices: HashMap<String, usize> = HashMap::new();
+    for element in columns.iter() {
+        column_indices.insert(element.column.clone(), headers.len());
+        headers.push(csv_quote(&element.column));
+
+        if values_mode == ValuesMode::Both && element.template.is_converted() {
+            headers.push(csv_quote(&converted_header(&element.column, element.template)));
+        }
+
+        if element.template.typ.is_checksum() {
+            headers.push(csv_quote(&valid_header(&element.column)));
+        }
+    }
 
     // Decode binary data, writing out to csv file.
     info!("Starting decoding");
     if rows {
-        output_file.write_all(&"type,description,value\n".to_string().as_bytes()).unwrap();
+        if values_mode == ValuesMode::Both {
+            output_file.write_all(b"type,description,value,converted\n").unwrap();
+        } else {
+            output_file.write_all(&"type,description,value\n".to_string().as_bytes()).unwrap();
+        }
     } else { // columns
-        let descriptions = templates.iter().map(|template| template.description.clone()).collect::<Vec<String>>();
-        let header_line = descriptions.join(",");
+        let header_line = headers.join(",");
         output_file.write_all(header_line.as_bytes()).unwrap();
         output_file.write_all(&b"\n"[..]).unwrap();
     }
+    // records are counted from zero when reporting errors in their values
+    let mut record_index: usize = 0;
+
+    // a file header before the first record is read past
+    if framing.skip > 0 {
+        match io::copy(&mut (&mut input).take(framing.skip), &mut io::sink()) {
+            Ok(skipped) if skipped == framing.skip => {},
+
+            Ok(_) => {
+                warn!("Input ended within its file header of {} bytes", framing.skip);
+                return Some(Ending::Complete);
+            },
+
+            Err(err) => {
+                error!("Could not read the file header: {}", err);
+                return None;
+            },
+        }
+    }
+
+    // with a sync marker, the start of any bytes skipped over looking for the next one
+    let mut skip_start: u64 = input.position();
+
     loop {
         let mut decoder_state = Default::default();
 
-        cursor.set_position(0);
+        if let Some(count) = framing.count {
+            if record_index >= count {
+                info!("Finished Reading {} Records", count);
+                return Some(Ending::Complete);
+            }
+        }
+
+        // records are a stride apart from the start of their sync marker, if they have one
+        let mut frame_start = input.position();
 
-        // if we get a read error, we are at the end of input, so just exit cleanly
-        if let Err(_) = input.read_exact(cursor.get_mut()) {
-            dbg!("Finished Reading File");
-            return Some(());
+        // with a sync marker, each record follows the next marker found, and any
+        // bytes before it are reported as skipped
+        if let Some(SyncMarker(marker)) = &framing.sync {
+            let found = match input.find(marker) {
+                Ok(found) => found,
+
+                Err(err) => {
+                    error!("Could not read input while looking for the sync marker: {}", err);
+                    return None;
+                },
+            };
+
+            let skip_end = found.unwrap_or_else(|| input.position());
+            if skip_end > skip_start {
+                error!("Skipped bytes {}..{} of the input, which do not hold a record following the sync marker", skip_start, skip_end);
+            }
+
+            match found {
+                Some(found) => frame_start = found,
+
+                None => {
+                    info!("Finished Reading File");
+                    return Some(Ending::Complete);
+                },
+            }
         }
 
-        for index in 0..templates.len() {
-            let template = &templates[index];
-            let field = read_field(&mut cursor, &mut decoder_state, &template).expect(&format!("Could not read field {}", template.description));
+        let record_start = input.position();
+        input.start_record();
+
+        // each record is written out once it has been completely read
+        let mut record: Vec<u8> = vec!();
+
+        // the bytes of the record each field was read from, and the checksums to
+        // check once the whole record is read, with where their rows end
+        let mut field_ranges: Vec<(String, Range<usize>)> = vec!();
+        let mut checksums: Vec<ReadChecksum> = vec!();
+
+        // the values of each column, with a value for each repetition of a repeated field
+        let mut values: Vec<Vec<String>> = vec![vec!(); headers.len()];
+
+        // the field that could not be read, if the record ended early
+        let mut unread_field: Option<String> = None;
+
+        let result = walk_record(templates, &mut |element| {
+            let template = element.template;
+
+            let field_offset = input.position();
+
+            // blobs are read using the length given by an earlier field
+            let field = match (template.typ, element.length) {
+                (FieldType::Blob(_), None) if template.record_length.is_some() => {
+                    error!("Length field of the record at byte offset {} does not give a length", record_start);
+                    return None;
+                },
+
+                (FieldType::Blob(_), None) => {
+                    error!("Length field of blob {} does not give a length", element.description);
+                    return None;
+                },
+
+                // the rest of a record is whatever its length leaves after the fields before it
+                (FieldType::Blob(_), Some(length)) if template.record_length.is_some() => {
+                    let offset = (field_offset - record_start) as usize;
+
+                    match length.checked_sub(offset) {
+                        Some(rest) => read_blob(&mut input, &mut decoder_state, template, rest),
+
+                        None => {
+                            error!("Record at byte offset {} has length {}, but its fields take {} bytes", record_start, length, offset);
+                            return None;
+                        },
+                    }
+                },
+
+                (FieldType::Blob(_), Some(length)) => read_blob(&mut input, &mut decoder_state, template, length),
+
+                _ => read_field(&mut input, &mut decoder_state, template),
+            };
+
+            let field = match field {
+                Some(field) => Field { description: element.description.clone(), ..field },
+
+                None => {
+                    unread_field = Some(element.description.clone());
+                    return None;
+                },
+            };
+
+            let field_range = (field_offset - record_start) as usize..(input.position() - record_start) as usize;
+            field_ranges.push((element.description.clone(), field_range.clone()));
+
+            // constants are checked against each record, and like padding are not written out
+            if let Some(constant) = &template.constant {
+                if field.value.to_string() != *constant {
+                    error!("Record {} has {} for constant field {} at byte offset {}, rather than {}",
+                           record_index, field.value.to_string(), element.description, field_offset, constant);
+                }
+            }
+
+            // padding is read past, but not written out
+            if template.is_hidden() {
+                return Some(field.value);
+            }
+
+            // converted fields, such as enumerations, are written out as their
+            // converted value, their raw value, or both
+            let converted = if template.is_converted() && values_mode != ValuesMode::Raw {
+                Some(convert_value(&field, template)?)
+            } else {
+                None
+            };
 
             // for rows, write out type, description, value
             if rows {
-                write_field(output_file, &field);
-                output_file.write_all(&b"\n"[..]).unwrap();
+                match (values_mode, converted) {
+                    (ValuesMode::Both, converted) => {
+                        write_field(&mut record, &field);
+                        record.write_all(b",").unwrap();
+                        if let Some(converted) = converted {
+                            record.write_all(csv_quote(&converted.to_string()).as_bytes()).unwrap();
+                        }
+                    },
+
+                    (_, Some(converted)) => write_field(&mut record, &Field { value: converted, ..field.clone() }),
+
+                    (_, None) => write_field(&mut record, &field),
+                }
+                record.write_all(&b"\n"[..]).unwrap();
             } else {
-                // for columns, write out value
-                output_file.write_all(&format!("{}", field.value.to_string()).as_bytes()).unwrap();
+                // for columns, collect values to write out as a line, escaping the values
+                // of repeated fields, which are joined into one column
+                let index = column_indices[&element.column];
+                let column_value = |value: &Value| if element.repeated { escape_repeated(&value.to_string()) } else { value.to_string() };
+
+                match (values_mode, converted) {
+                    (ValuesMode::Both, Some(converted)) => {
+                        values[index].push(column_value(&field.value));
+                        values[index + 1].push(column_value(&converted));
+                    },
 
-                // only write a ',' if this is not the last entry
-                if index != templates.len() - 1 {
-                    output_file.write_all(&b","[..]).unwrap();
+                    (_, Some(converted)) => values[index].push(column_value(&converted)),
+
+                    (_, None) => values[index].push(column_value(&field.value)),
                 }
             }
+
+            if template.typ.is_checksum() {
+                checksums.push(ReadChecksum {
+                    template,
+                    description: element.description.clone(),
+                    column: element.column.clone(),
+                    value: field.value.clone(),
+                    range: field_range,
+                    row_end: record.len(),
+                });
+            }
+
+            Some(field.value)
+        });
+
+        // with a sync marker, a record that can not be read is skipped, and the next
+        // marker is looked for from just after the start of the last one, unless
+        // the input ended within the record without holding another marker
+        if let (None, Some(SyncMarker(marker))) = (result, &framing.sync) {
+            let mut rest = marker[1..].to_vec();
+            rest.extend_from_slice(input.record());
+
+            let partial_record = input.at_end() &&
+                                 unread_field.is_some() &&
+                                 !rest.windows(marker.len()).any(|window| window == marker.as_slice());
+
+            if !partial_record {
+                match &unread_field {
+                    Some(description) if !input.at_end() => {
+                        error!("Could not read field {} of the record at byte offset {}, so looking for the next sync marker", description, record_start);
+                    },
+
+                    None => {
+                        error!("Could not decode the record at byte offset {}, so looking for the next sync marker", record_start);
+                    },
+
+                    _ => {},
+                }
+
+                input.unread(&rest);
+
+                skip_start = frame_start;
+                continue;
+            }
+        }
+
+        match (result, unread_field) {
+            (Some(()), _) => {},
+
+            // if we run out of input at the start of a record, we are at the
+            // end of input, so just exit cleanly
+            (None, Some(_)) if input.at_end() && input.position() == frame_start => {
+                info!("Finished Reading File");
+                return Some(Ending::Complete);
+            },
+
+            // a partial record at the end of the input is reported, and is either decoded
+            // as far as it goes or left out, with its bytes given back along with its offset
+            (None, Some(_)) if input.at_end() => {
+                let mut remainder = match &framing.sync {
+                    Some(SyncMarker(marker)) => marker.clone(),
+                    None => vec!(),
+                };
+                remainder.extend_from_slice(input.record());
+
+                error!("Input ended within the record at byte offset {}, leaving {} bytes that do not form a whole record",
+                       frame_start, remainder.len());
+
+                if partial.decode {
+                    if !rows {
+                        record.write_all(column_line(&values).as_bytes()).unwrap();
+                    }
+
+                    output_file.write_all(&record).unwrap();
+                    output_file.flush().unwrap();
+                }
+
+                return Some(Ending::Partial(frame_start, remainder));
+            },
+
+            (None, Some(description)) => {
+                error!("Could not read field {} of the record at byte offset {}", description, record_start);
+                return None;
+            },
+
+            (None, None) => {
+                error!("Could not decode the record at byte offset {}", record_start);
+                return None;
+            },
+        }
+
+        // checksums are checked against the bytes of the whole record, and are
+        // flagged as valid or not in the row or column following them
+        for ReadChecksum { template, description, column, value, range, row_end } in checksums.into_iter().rev() {
+            let kind = match template.typ {
+                FieldType::Checksum(kind, _) => kind,
+                _ => unreachable!(),
+            };
+
+            let expected = match record_checksum(kind, &template.span, input.record(), &field_ranges, &range) {
+                Some(expected) => expected,
+
+                None => {
+                    error!("Could not compute checksum {} of the record at byte offset {}", description, record_start);
+                    return None;
+                },
+            };
+
+            let valid = value.as_integer() == Some(expected as i128);
+
+            if !valid {
+                error!("Record {} has checksum {} of {} at byte offset {}, but its bytes give {}",
+                       record_index, description, value.to_string(), record_start + range.start as u64, expected);
+            }
+
+            if rows {
+                let mut flag = format!("{},{},{}", VALID_ROW_TYPE, csv_quote(&valid_header(&description)), valid);
+                if values_mode == ValuesMode::Both {
+                    flag.push(',');
+                }
+                flag.push('\n');
+
+                record.splice(row_end..row_end, flag.into_bytes());
+            } else {
+                values[column_indices[&column] + 1].insert(0, valid.to_string());
+            }
         }
 
         if !rows {
-            output_file.write_all(&b"\n"[..]).unwrap();
+            record.write_all(column_line(&values).as_bytes()).unwrap();
         }
+
+        // records shorter than the stride are followed by padding up to the next record,
+        // which may be cut short at the end of the input
+        if let Some(stride) = framing.stride {
+            let length = input.position() - frame_start;
+
+            if length > stride as u64 {
+                error!("Record at byte offset {} is {} bytes, which is longer than the stride of {} bytes", frame_start, length, stride);
+                return None;
+            }
+
+            if let Err(err) = io::copy(&mut (&mut input).take(stride as u64 - length), &mut io::sink()) {
+                error!("Could not read the padding after the record at byte offset {}: {}", frame_start, err);
+                return None;
+            }
+        }
+
+        // each record is flushed once written, so streams are written out as they are decoded
+        output_file.write_all(&record).unwrap();
+        output_file.flush().unwrap();
+
+        record_index += 1;
+        skip_start = input.position();
     }
 }
 
+/// The line of a column based file holding the values of each column, with the
+/// values of repeated fields joined into a single column.
+fn column_line(values: &[Vec<String>]) -> String {
+    let line = values.iter()
+                     .map(|column_values| csv_quote(&column_values.join(";")))
+                     .collect::<Vec<String>>()
+                     .join(",");
+
+    format!("{}\n", line)
+}
+
+/// A checksum read from a record, to be checked once the whole record has been read.
+struct ReadChecksum<'a> {
+    template: &'a Template,
+    description: String,
+    column: String,
+    value: Value,
+
+    /// the bytes of the record the checksum was read from
+    range: Range<usize>,
+
+    /// the end of the checksum's row, where its valid flag is written in row mode
+    row_end: usize,
+}
+
 fn read_field<R>(reader: &mut R,
                  bit_buffer: &mut BitBuffer,
                  template: &Template) -> Option<Field>
@@ -68,6 +455,60 @@ fn read_field<R>(reader: &mut R,
 
     let value: Value;
 
+    // varints are read byte by byte until their last byte, outside of any bit field
+    if let FieldType::Varint(encoding) = template.typ {
+        if !bit_buffer.is_empty() {
+            error!("Varint field {} can not be placed within a bit field", template.description);
+            return None;
+        }
+
+        let value = match encoding {
+            VarintEncoding::ULeb128 => Value::Uint128(varint::read_unsigned(reader)?),
+            VarintEncoding::SLeb128 => Value::Int128(varint::read_signed(reader)?),
+            VarintEncoding::ZigZag => Value::Int128(varint::read_zigzag(reader)?),
+        };
+
+        return Some(Field {
+            value,
+            typ: template.typ,
+            description: template.description.clone(),
+        });
+    }
+
+    // time codes are read directly as bytes, outside of any bit field
+    if let FieldType::Time(code, epoch) = template.typ {
+        if !bit_buffer.is_empty() {
+            error!("Time field {} can not be placed within a bit field", template.description);
+            return None;
+        }
+
+        let mut bytes = vec![0; code.num_bytes()];
+        reader.read_exact(&mut bytes).ok()?;
+
+        return Some(Field {
+            value: Value::Time(code.decode(epoch, &bytes)?),
+            typ: template.typ,
+            description: template.description.clone(),
+        });
+    }
+
+    // strings are read directly as bytes, outside of any bit field
+    if let FieldType::Str(num_bytes, padding, encoding) = template.typ {
+        if !bit_buffer.is_empty() {
+            error!("String field {} can not be placed within a bit field", template.description);
+            return None;
+        }
+
+        let mut bytes = vec![0; num_bytes];
+        reader.read_exact(&mut bytes).ok()?;
+
+        return Some(Field {
+            value: Value::Str(encoding.decode(padding.trim(&bytes))),
+            typ: template.typ,
+            description: template.description.clone(),
+        });
+    }
+
     if bit_buffer.is_empty() {
         for _ in 0..template.typ.bit_size().num_bytes() {
             let byte = reader.read_u8().ok()?;
@@ -88,6 +529,79 @@ fn read_field<R>(reader: &mut R,
     })
 }
 
+/// Read a blob of the given length, formatting its bytes as text.
+fn read_blob<R: Read>(reader: &mut R, bit_buffer: &mut BitBuffer, template: &Template, length: usize) -> Option<Field> {
+    let format = match template.typ {
+        FieldType::Blob(format) => format,
+        _ => return None,
+    };
+
+    // blobs are read directly as bytes, outside of any bit field
+    if !bit_buffer.is_empty() {
+        error!("Blob field {} can not be placed within a bit field", template.description);
+        return None;
+    }
+
+    // the bytes are read as they arrive, so a bad length can not use up memory
+    let mut bytes = vec!();
+    reader.take(length as u64).read_to_end(&mut bytes).ok()?;
+
+    if bytes.len() != length {
+        return None;
+    }
+
+    Some(Field {
+        value: Value::Str(format.format(&bytes)),
+        typ: template.typ,
+        description: template.description.clone(),
+    })
+}
+
+/// The converted value of a field, which is the label of an enumerated
+/// field, the engineering value of a calibrated field, or the time of a timestamp.
+fn convert_value(field: &Field, template: &Template) -> Option<Value> {
+    if let Some(enumeration) = &template.enumeration {
+        return label_value(field, enumeration);
+    }
+
+    if let Some(calibration) = &template.calibration {
+        return Some(Value::Double(calibration.convert(field.value.as_float()?)));
+    }
+
+    // timestamps of whole counts are exact, while float counts are given to nanoseconds
+    if let Some(scale) = &template.timestamp {
+        return match field.value.as_integer() {
+            Some(counts) => Some(Value::Time(scale.time(counts))),
+            None => Some(Value::Time(scale.time_from_float(field.value.as_float()?))),
+        };
+    }
+
+    Some(field.value.clone())
+}
+
+/// The label of an enumerated field's value.
+fn label_value(field: &Field, enumeration: &Enumeration) -> Option<Value> {
+    let raw_value = field.value.as_integer()?;
+
+    match enumeration.label(raw_value) {
+        Some(label) => Some(Value::Str(label)),
+
+        None => {
+            error!("Value {} of field {} is not in its enumeration", raw_value, field.description);
+            None
+        },
+    }
+}
+
+/// The column header for the converted value of a field, when writing out
+/// both raw and converted values.
+fn converted_header(column: &str, template: &Template) -> String {
+    match &template.unit {
+        Some(unit) => format!("{} ({})", column, unit),
+        None => format!("{} (converted)", column),
+    }
+}
+
 fn write_field<W: Write>(writer: &mut W, field: &Field) {
     writer.write_all(&field.to_record().as_bytes()).unwrap();
 }
@@ -256,7 +770,7 @@ fn test_read_field_le_bitfields_byte() {
 
     let mut cursor = Cursor::new(buffer.as_mut_slice());
 
-    let typ = FieldType::Uint(1, endianness, BitSize::Bits8);
+    let typ = FieldType::Uint(1, endianness, BitSize(8));
 
     let mut bit_buffer = Default::default();
 
@@ -281,30 +795,30 @@ fn test_read_field_be_bitfields_word() {
 
     let field = read_field(&mut cursor,
                            &mut bit_buffer,
-                           &Template::new(FieldType::Uint(4, endianness, BitSize::Bits16), descr.clone()));
+                           &Template::new(FieldType::Uint(4, endianness, BitSize(16)), descr.clone()));
     assert_eq!(field, Some(Field { value: Value::Uint8(1),
-                                   typ: FieldType::Uint(4, endianness, BitSize::Bits16),
+                                   typ: FieldType::Uint(4, endianness, BitSize(16)),
                                    description: descr.clone() }));
 
     let field = read_field(&mut cursor,
                            &mut bit_buffer,
-                           &Template::new(FieldType::Uint(8, endianness, BitSize::Bits16), descr.clone()));
+                           &Template::new(FieldType::Uint(8, endianness, BitSize(16)), descr.clone()));
     assert_eq!(field, Some(Field { value: Value::Uint8(0x23),
-                                   typ: FieldType::Uint(8, endianness, BitSize::Bits16),
+                                   typ: FieldType::Uint(8, endianness, BitSize(16)),
                                    description: descr.clone() }));
 
     let field = read_field(&mut cursor,
                            &mut bit_buffer,
-                           &Template::new(FieldType::Uint(2, endianness, BitSize::Bits16), descr.clone()));
+                           &Template::new(FieldType::Uint(2, endianness, BitSize(16)), descr.clone()));
     assert_eq!(field, Some(Field { value: Value::Uint8(0x01),
-                                   typ: FieldType::Uint(2, endianness, BitSize::Bits16),
+                                   typ: FieldType::Uint(2, endianness, BitSize(16)),
                                    description: descr.clone() }));
 
     let field = read_field(&mut cursor,
                            &mut bit_buffer,
-                           &Template::new(FieldType::Uint(2, endianness, BitSize::Bits16), descr.clone()));
+                           &Template::new(FieldType::Uint(2, endianness, BitSize(16)), descr.clone()));
     assert_eq!(field, Some(Field { value: Value::Uint8(0x00),
-                                   typ: FieldType::Uint(2, endianness, BitSize::Bits16),
+                                   typ: FieldType::Uint(2, endianness, BitSize(16)),
                                    description: descr.clone() }));
 }
 
@@ -321,30 +835,654 @@ fn test_read_field_le_bitfields_word() {
 
     let field = read_field(&mut cursor,
                            &mut bit_buffer,
-                           &Template::new(FieldType::Uint(4, endianness, BitSize::Bits16), descr.clone()));
+                           &Template::new(FieldType::Uint(4, endianness, BitSize(16)), descr.clone()));
     assert_eq!(field, Some(Field { value: Value::Uint8(5),
-                                   typ: FieldType::Uint(4, endianness, BitSize::Bits16),
+                                   typ: FieldType::Uint(4, endianness, BitSize(16)),
                                    description: descr.clone() }));
 
     let field = read_field(&mut cursor,
                            &mut bit_buffer,
-                           &Template::new(FieldType::Uint(8, endianness, BitSize::Bits16), descr.clone()));
+                           &Template::new(FieldType::Uint(8, endianness, BitSize(16)), descr.clone()));
     assert_eq!(field, Some(Field { value: Value::Uint8(0x23),
-                                   typ: FieldType::Uint(8, endianness, BitSize::Bits16),
+                                   typ: FieldType::Uint(8, endianness, BitSize(16)),
                                    description: descr.clone() }));
 
     let field = read_field(&mut cursor,
                            &mut bit_buffer,
-                           &Template::new(FieldType::Uint(2, endianness, BitSize::Bits16), descr.clone()));
+                           &Template::new(FieldType::Uint(2, endianness, BitSize(16)), descr.clone()));
     assert_eq!(field, Some(Field { value: Value::Uint8(0x01),
-                                   typ: FieldType::Uint(2, endianness, BitSize::Bits16),
+                                   typ: FieldType::Uint(2, endianness, BitSize(16)),
                                    description: descr.clone() }));
 
     let field = read_field(&mut cursor,
                            &mut bit_buffer,
-                           &Template::new(FieldType::Uint(2, endianness, BitSize::Bits16), descr.clone()));
+                           &Template::new(FieldType::Uint(2, endianness, BitSize(16)), descr.clone()));
     assert_eq!(field, Some(Field { value: Value::Uint8(0x00),
-                                   typ: FieldType::Uint(2, endianness, BitSize::Bits16),
+                                   typ: FieldType::Uint(2, endianness, BitSize(16)),
                                    description: descr.clone() }));
 }
 
+
+#[test]
+fn test_decode_array_columns() {
+    let mut input: &[u8] = &[0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x12, 0x34];
+
+    let endianness = Endianness::Big;
+    let templates = vec!(Template::array(FieldType::u16(endianness), "samples".to_string(), 3),
+                         Template::array(FieldType::Uint(4, endianness, BitSize(16)), "nibbles".to_string(), 4));
+
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "samples[0],samples[1],samples[2],nibbles[0],nibbles[1],nibbles[2],nibbles[3]\n1,2,3,1,2,3,4\n");
+}
+
+#[test]
+fn test_decode_array_rows() {
+    let mut input: &[u8] = &[0x01, 0x02];
+
+    let templates = vec!(Template::array(FieldType::u8(Endianness::Big), "bytes".to_string(), 2));
+
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "type,description,value\nuint8_be,bytes[0],1\nuint8_be,bytes[1],2\n");
+}
+
+#[test]
+fn test_read_field_string() {
+    let mut buffer: Vec<u8> = b"abc\0\0\0a b   ".to_vec();
+
+    let descr = "Field".to_string();
+
+    let nul_padded = Template::new(FieldType::string(6), descr.clone());
+    let space_padded = Template::new(FieldType::Str(6, Padding::Space, TextEncoding::Ascii), descr.clone());
+
+    let mut cursor = Cursor::new(buffer.as_mut_slice());
+
+    let mut bit_buffer = Default::default();
+
+    assert_eq!(read_field(&mut cursor, &mut bit_buffer, &nul_padded), Some(Field::string("abc", 6, descr.clone())));
+    assert_eq!(read_field(&mut cursor, &mut bit_buffer, &space_padded).unwrap().value, Value::Str("a b".to_string()));
+}
+
+#[test]
+fn test_decode_string_quoted() {
+    let mut input: &[u8] = b"a,b\0";
+
+    let templates = vec!(Template::new(FieldType::string(4), "name".to_string()));
+
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+
+    assert_eq!(String::from_utf8(output).unwrap(), "name\n\"a,b\"\n");
+}
+
+#[test]
+fn test_decode_skips_padding() {
+    let mut input: &[u8] = &[0x01, 0xFF, 0xFF, 0xA2];
+
+    let endianness = Endianness::Big;
+    let templates = vec!(Template::new(FieldType::u8(endianness), "a".to_string()),
+                         Template::array(FieldType::Pad(8, endianness, BitSize(8)), "spare".to_string(), 2),
+                         Template::new(FieldType::Pad(4, endianness, BitSize(8)), "reserved".to_string()),
+                         Template::new(FieldType::Uint(4, endianness, BitSize(8)), "b".to_string()));
+
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "a,b\n1,2\n");
+
+    let mut input: &[u8] = &[0x01, 0xFF, 0xFF, 0xA2];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "type,description,value\nuint8_be,a,1\nuint4_be:8,b,2\n");
+}
+
+#[test]
+fn test_read_field_bit_sizes() {
+    let mut buffer: Vec<u8> = vec!(0x01, 0x02, 0x03, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE, 0x00, 0x10, 0x02, 0x05, 0x06, 0x00);
+
+    let descr = "Field".to_string();
+
+    let uint24 = FieldType::Uint(24, Endianness::Little, BitSize(24));
+    let int48 = FieldType::Int(48, Endianness::Big, BitSize(48));
+    let uint12 = FieldType::Uint(12, Endianness::Big, BitSize(24));
+    let byte = FieldType::u8(Endianness::Big);
+    let word = FieldType::u16(Endianness::Little);
+
+    let mut cursor = Cursor::new(buffer.as_mut_slice());
+
+    let mut bit_buffer = Default::default();
+
+    let mut read_value = |typ| read_field(&mut cursor, &mut bit_buffer, &Template::new(typ, descr.clone())).unwrap().value;
+
+    assert_eq!(read_value(uint24), Value::Uint32(0x030201));
+    assert_eq!(read_value(int48), Value::Int64(-2));
+    assert_eq!(read_value(uint12), Value::Uint16(1));
+    assert_eq!(read_value(uint12), Value::Uint16(2));
+
+    // mixing endianness must not leave bits from earlier fields behind
+    assert_eq!(read_value(byte), Value::Uint8(5));
+    assert_eq!(read_value(word), Value::Uint16(6));
+}
+
+#[test]
+fn test_read_field_128_bits() {
+    let mut buffer: Vec<u8> = (1..=16).chain(1..=16).chain(vec!(0xFF; 12)).collect();
+
+    let descr = "Field".to_string();
+
+    let mut cursor = Cursor::new(buffer.as_mut_slice());
+
+    let mut bit_buffer = Default::default();
+
+    let mut read_value = |typ| read_field(&mut cursor, &mut bit_buffer, &Template::new(typ, descr.clone())).unwrap().value;
+
+    assert_eq!(read_value(FieldType::u128(Endianness::Big)), Value::Uint128(0x0102030405060708090A0B0C0D0E0F10));
+    assert_eq!(read_value(FieldType::u128(Endianness::Little)), Value::Uint128(0x100F0E0D0C0B0A090807060504030201));
+    assert_eq!(read_value(FieldType::Int(96, Endianness::Big, BitSize(96))), Value::Int128(-1));
+}
+
+#[test]
+fn test_decode_small_floats() {
+    let mut input: &[u8] = &[0x2E, 0x66, 0x00, 0x7C, 0x7E, 0x00, 0x49, 0x40];
+
+    let templates = vec!(Template::new(FieldType::half(Endianness::Big), "a".to_string()),
+                         Template::new(FieldType::half(Endianness::Little), "b".to_string()),
+                         Template::new(FieldType::half(Endianness::Big), "c".to_string()),
+                         Template::new(FieldType::bfloat16(Endianness::Little), "d".to_string()));
+
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+
+    assert_eq!(String::from_utf8(output).unwrap(), "a,b,c,d\n0.099975586,inf,NaN,3.140625\n");
+}
+
+#[test]
+fn test_read_field_bool() {
+    let mut buffer: Vec<u8> = vec!(0x01, 0x80, 0x02);
+
+    let descr = "Field".to_string();
+
+    let mut cursor = Cursor::new(buffer.as_mut_slice());
+
+    let mut bit_buffer = Default::default();
+
+    let mut read_value = |typ| read_field(&mut cursor, &mut bit_buffer, &Template::new(typ, descr.clone())).map(|field| field.value);
+
+    assert_eq!(read_value(FieldType::bool(Endianness::Big)), Some(Value::Bool(true)));
+    assert_eq!(read_value(FieldType::Bool(1, Endianness::Big, BitSize(8))), Some(Value::Bool(true)));
+    assert_eq!(read_value(FieldType::Bool(7, Endianness::Big, BitSize(8))), Some(Value::Bool(false)));
+
+    // multi-bit booleans must be 0 or 1
+    assert_eq!(read_value(FieldType::bool(Endianness::Big)), None);
+}
+
+#[test]
+fn test_decode_enumeration() {
+    let endianness = Endianness::Big;
+
+    let mut mode = Template::new(FieldType::u8(endianness), "mode".to_string());
+    mode.enumeration = Enumeration::parse("0=IDLE;1=SAFE;2=SCIENCE");
+
+    let mut kind = Template::new(FieldType::u8(endianness), "kind".to_string());
+    kind.enumeration = Enumeration::parse("0=TM;*=");
+
+    let templates = vec!(mode, kind);
+
+    let mut input: &[u8] = &[0x02, 0x00, 0x01, 0x07];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "mode,kind\nSCIENCE,TM\nSAFE,7\n");
+
+    let mut input: &[u8] = &[0x02, 0x00];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "type,description,value\nuint8_be,mode,SCIENCE\nuint8_be,kind,TM\n");
+
+    // values not in the enumeration are an error without a fallback
+    let mut input: &[u8] = &[0x03, 0x00];
+    let mut output: Vec<u8> = Vec::new();
+    assert!(decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).is_none());
+}
+
+#[test]
+fn test_decode_calibration() {
+    let endianness = Endianness::Big;
+
+    let mut volts = Template::new(FieldType::u8(endianness), "volts".to_string());
+    volts.calibration = Some(Calibration::linear(0.5, -1.0));
+    volts.unit = Some("V".to_string());
+
+    let mut mode = Template::new(FieldType::u8(endianness), "mode".to_string());
+    mode.enumeration = Enumeration::parse("0=IDLE;1=SAFE");
+
+    let templates = vec!(volts, mode, Template::new(FieldType::u8(endianness), "count".to_string()));
+
+    let decode_with = |rows, values_mode| {
+        let mut input: &[u8] = &[0x05, 0x01, 0x02];
+        let mut output: Vec<u8> = Vec::new();
+        decode(&mut input, &mut output, &templates, rows, values_mode, &Framing::default(), &PartialRecord::default()).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+
+    assert_eq!(decode_with(false, ValuesMode::Converted), "volts,mode,count\n1.5,SAFE,2\n");
+    assert_eq!(decode_with(false, ValuesMode::Raw), "volts,mode,count\n5,1,2\n");
+    assert_eq!(decode_with(false, ValuesMode::Both), "volts,volts (V),mode,mode (converted),count\n5,1.5,1,SAFE,2\n");
+
+    assert_eq!(decode_with(true, ValuesMode::Converted),
+               "type,description,value\nuint8_be,volts,1.5\nuint8_be,mode,SAFE\nuint8_be,count,2\n");
+    assert_eq!(decode_with(true, ValuesMode::Both),
+               "type,description,value,converted\nuint8_be,volts,5,1.5\nuint8_be,mode,1,SAFE\nuint8_be,count,2,\n");
+}
+
+#[test]
+fn test_decode_varints() {
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "a".to_string()),
+                         Template::new(FieldType::Varint(VarintEncoding::ULeb128), "b".to_string()),
+                         Template::new(FieldType::Varint(VarintEncoding::SLeb128), "c".to_string()),
+                         Template::new(FieldType::Varint(VarintEncoding::ZigZag), "d".to_string()));
+
+    let mut input: &[u8] = &[0x01, 0xAC, 0x02, 0x7F, 0x03,
+                             0x02, 0x00, 0xC0, 0xBB, 0x78, 0x80, 0x01];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "a,b,c,d\n1,300,-1,-2\n2,0,-123456,64\n");
+}
+
+#[test]
+fn test_decode_partial_record() {
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "a".to_string()),
+                         Template::new(FieldType::Varint(VarintEncoding::ULeb128), "b".to_string()));
+
+    // the last record ends within its varint, and is not written out
+    let mut input: &[u8] = &[0x01, 0x02, 0x03, 0x80];
+    let mut output: Vec<u8> = Vec::new();
+    let ending = decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default());
+    assert_eq!(String::from_utf8(output).unwrap(), "type,description,value\nuint8_be,a,1\nuleb128,b,2\n");
+    assert_eq!(ending, Some(Ending::Partial(2, vec!(0x03, 0x80))));
+
+    // or is decoded as far as it goes
+    let mut input: &[u8] = &[0x01, 0x02, 0x03, 0x80];
+    let mut output: Vec<u8> = Vec::new();
+    let partial = PartialRecord { decode: true, ..Default::default() };
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &partial).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "a,b\n1,2\n3,\n");
+
+    // records ending with the input are complete
+    let mut input: &[u8] = &[0x01, 0x02];
+    let mut output: Vec<u8> = Vec::new();
+    let ending = decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &partial);
+    assert_eq!(ending, Some(Ending::Complete));
+
+    // with a sync marker, the partial record includes its marker
+    let mut input: &[u8] = &[0xEB, 0x90, 0x01, 0x02, 0xEB, 0x90, 0x03];
+    let mut output: Vec<u8> = Vec::new();
+    let framing = Framing { sync: Some(SyncMarker(vec!(0xEB, 0x90))), ..Default::default() };
+    let ending = decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing, &PartialRecord::default());
+    assert_eq!(ending, Some(Ending::Partial(4, vec!(0xEB, 0x90, 0x03))));
+}
+
+#[test]
+fn test_decode_bcd() {
+    let endianness = Endianness::Big;
+    let templates = vec!(Template::new(FieldType::Bcd(16, endianness, BitSize(16), BcdPacking::Packed), "year".to_string()),
+                         Template::new(FieldType::Bcd(4, endianness, BitSize(8), BcdPacking::Packed), "tens".to_string()),
+                         Template::new(FieldType::Bcd(4, endianness, BitSize(8), BcdPacking::Packed), "ones".to_string()),
+                         Template::new(FieldType::Bcd(16, Endianness::Little, BitSize(16), BcdPacking::Unpacked), "day".to_string()));
+
+    let mut input: &[u8] = &[0x20, 0x24, 0x59, 0x01, 0x03];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "year,tens,ones,day\n2024,5,9,31\n");
+
+    // nibbles above 9 are an error
+    let mut input: &[u8] = &[0x20, 0x2A, 0x59, 0x01, 0x03];
+    let mut output: Vec<u8> = Vec::new();
+    assert!(decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).is_none());
+}
+
+#[test]
+fn test_decode_time_codes() {
+    let templates = vec!(Template::new(FieldType::Time(TimeCode::Cuc(4, 2), CCSDS_EPOCH), "cuc".to_string()),
+                         Template::new(FieldType::Time(TimeCode::Cds(2, 0), Epoch(0)), "cds".to_string()));
+
+    let mut input: &[u8] = &[0x00, 0x00, 0x00, 0x3C, 0x40, 0x00,
+                             0x00, 0x01, 0x00, 0x00, 0x03, 0xE8];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "type,description,value\ncuc4.2,cuc,1958-01-01T00:01:00.250000Z\ncds2@1970-01-01,cds,1970-01-02T00:00:01.000Z\n");
+}
+
+#[test]
+fn test_decode_timestamps() {
+    let mut seconds = Template::new(FieldType::u32(Endianness::Big), "seconds".to_string());
+    seconds.timestamp = TimeScale::parse("s");
+
+    let mut millis = Template::new(FieldType::u64(Endianness::Little), "millis".to_string());
+    millis.timestamp = TimeScale::parse("ms@2000-01-01");
+
+    let mut float_seconds = Template::new(FieldType::double(Endianness::Big), "float seconds".to_string());
+    float_seconds.timestamp = TimeScale::parse("s");
+
+    let templates = vec!(seconds, millis, float_seconds);
+
+    let mut input: &[u8] = &[0x00, 0x00, 0x00, 0x3C,
+                             0xE8, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                             0x3F, 0xF8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Both, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "seconds,seconds (converted),millis,millis (converted),float seconds,float seconds (converted)\n\
+                60,1970-01-01T00:01:00Z,1000,2000-01-01T00:00:01.000Z,1.5,1970-01-01T00:00:01.500000000Z\n");
+}
+
+#[test]
+fn test_decode_count_field() {
+    let byte = FieldType::u8(Endianness::Big);
+
+    let mut samples = Template::new(FieldType::u16(Endianness::Big), "samples".to_string());
+    samples.count_field = Some(FieldRef::new("count"));
+
+    let mut entries = Template::structure("entry.csv".to_string(),
+                                          vec!(Template::new(byte, "id".to_string()), Template::new(byte, "value".to_string())),
+                                          "entries".to_string(),
+                                          None);
+    entries.count_field = Some(FieldRef::new("num entries"));
+
+    let templates = vec!(Template::new(byte, "count".to_string()),
+                         samples,
+                         Template::new(byte, "num entries".to_string()),
+                         entries);
+
+    let mut input: &[u8] = &[0x02, 0x00, 0x01, 0x00, 0x02, 0x01, 0x07, 0x08,
+                             0x00, 0x00];
+
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "count,samples,num entries,entries.id,entries.value\n2,1;2,1,7,8\n0,,0,,\n");
+
+    let mut input: &[u8] = &[0x01, 0x00, 0x01, 0x02, 0x07, 0x08, 0x09, 0x0A];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "type,description,value\nuint8_be,count,1\nuint16_be,samples[0],1\nuint8_be,num entries,2\n\
+                uint8_be,entries[0].id,7\nuint8_be,entries[0].value,8\nuint8_be,entries[1].id,9\nuint8_be,entries[1].value,10\n");
+}
+
+#[test]
+fn test_decode_repeated_strings_round_trip() {
+    let mut names = Template::new(FieldType::string(4), "names".to_string());
+    names.count_field = Some(FieldRef::new("count"));
+
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "count".to_string()), names);
+
+    // separators within the values of a repeated field are escaped, so they encode back to the same values
+    let bytes: &[u8] = b"\x02a;b\0c\\d\0";
+    let mut input = bytes;
+    let mut columns: Vec<u8> = Vec::new();
+    decode(&mut input, &mut columns, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(columns.clone()).unwrap(), "count,names\n2,a\\;b;c\\\\d\n");
+
+    let mut input: &[u8] = &columns;
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, bytes);
+}
+
+#[test]
+fn test_decode_blobs() {
+    let mut data = Template::new(FieldType::Blob(BlobFormat::Hex), "data".to_string());
+    data.length_field = Some(FieldRef::parse("packetLen+1"));
+
+    let mut encoded = Template::new(FieldType::Blob(BlobFormat::Base64), "encoded".to_string());
+    encoded.length_field = Some(FieldRef::new("packetLen"));
+
+    let mut name = Template::new(FieldType::Blob(BlobFormat::Text(TextEncoding::Utf8)), "name".to_string());
+    name.length_field = Some(FieldRef::new("nameLen"));
+
+    let templates = vec!(Template::new(FieldType::u16(Endianness::Big), "packetLen".to_string()),
+                         data,
+                         encoded,
+                         Template::new(FieldType::u8(Endianness::Big), "nameLen".to_string()),
+                         name);
+
+    let mut input: &[u8] = &[0x00, 0x01, 0x01, 0xAB, 0x66, 0x03, 0x68, 0xC3, 0xA9,
+                             0x00, 0x00, 0xFF, 0x00];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "packetLen,data,encoded,nameLen,name\n1,01AB,Zg==,3,h\u{e9}\n0,FF,,0,\n");
+
+    // a blob longer than the rest of the input ends the record
+    let mut input: &[u8] = &[0x00, 0x05, 0x01, 0x02];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "type,description,value\n");
+}
+
+#[test]
+fn test_decode_present_if() {
+    let mut time = Template::new(FieldType::u16(Endianness::Big), "time".to_string());
+    time.present_if = Some(Condition::parse("secHeaderFlag == 1").unwrap());
+
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "secHeaderFlag".to_string()),
+                         time,
+                         Template::new(FieldType::u8(Endianness::Big), "apid".to_string()));
+
+    let mut input: &[u8] = &[0x01, 0x01, 0x02, 0x03, 0x00, 0x04];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "secHeaderFlag,time,apid\n1,258,3\n0,,4\n");
+
+    let mut input: &[u8] = &[0x00, 0x04];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "type,description,value\nuint8_be,secHeaderFlag,0\nuint8_be,apid,4\n");
+
+    // conditions may only refer to earlier fields
+    let mut early = Template::new(FieldType::u8(Endianness::Big), "early".to_string());
+    early.present_if = Some(Condition::parse("later").unwrap());
+    let templates = vec!(early, Template::new(FieldType::u8(Endianness::Big), "later".to_string()));
+
+    let mut input: &[u8] = &[0x01, 0x02];
+    let mut output: Vec<u8> = Vec::new();
+    assert!(decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).is_none());
+}
+
+#[test]
+fn test_decode_union() {
+    let byte = FieldType::u8(Endianness::Big);
+
+    let branch = |value, fields| Branch { value, structure: Structure { name: "branch.csv".to_string(), fields } };
+    let branches = vec!(branch(Some(1), vec!(Template::new(FieldType::u16(Endianness::Big), "temperature".to_string()))),
+                        branch(Some(2), vec!(Template::new(byte, "mode".to_string()))));
+
+    let templates = vec!(Template::new(byte, "apid".to_string()),
+                         Template::union("bodies.csv".to_string(), FieldRef::new("apid"), branches, "body".to_string()),
+                         Template::new(byte, "crc".to_string()));
+
+    let mut input: &[u8] = &[0x01, 0x00, 0x10, 0xAA, 0x02, 0x03, 0xBB];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "apid,body.temperature,body.mode,crc\n1,16,,170\n2,,3,187\n");
+
+    let mut input: &[u8] = &[0x02, 0x03, 0xBB];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "type,description,value\nuint8_be,apid,2\nuint8_be,body.mode,3\nuint8_be,crc,187\n");
+
+    // without a default branch, a value with no branch is an error
+    let mut input: &[u8] = &[0x03, 0x00, 0x00];
+    let mut output: Vec<u8> = Vec::new();
+    assert!(decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).is_none());
+}
+
+#[test]
+fn test_decode_constant() {
+    let mut sync = Template::new(FieldType::u16(Endianness::Big), "sync".to_string());
+    sync.constant = Some("6900".to_string());
+
+    let templates = vec!(sync, Template::new(FieldType::u8(Endianness::Big), "value".to_string()));
+
+    // constants are left out of the output, and mismatches are reported without stopping
+    let mut input: &[u8] = &[0x1A, 0xF4, 0x05, 0x1A, 0xF5, 0x06];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "value\n5\n6\n");
+
+    let mut input: &[u8] = &[0x1A, 0xF4, 0x05];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "type,description,value\nuint8_be,value,5\n");
+}
+
+#[test]
+fn test_decode_checksum() {
+    let mut check = Template::new(FieldType::Checksum(ChecksumKind::Xor8, Endianness::Big), "check".to_string());
+    check.span = Span::parse("data");
+
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "version".to_string()),
+                         Template::array(FieldType::u8(Endianness::Big), "data".to_string(), 2),
+                         Template::new(FieldType::Checksum(ChecksumKind::Sum16, Endianness::Little), "sum".to_string()),
+                         check);
+
+    // each checksum is followed by whether it is valid
+    let mut input: &[u8] = &[0x01, 0x02, 0x03, 0x06, 0x00, 0x01,
+                             0x01, 0x02, 0x04, 0x06, 0x00, 0x01];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "version,data[0],data[1],sum,sum valid,check,check valid\n1,2,3,6,true,1,true\n1,2,4,6,false,1,false\n");
+
+    let mut input: &[u8] = &[0x01, 0x02, 0x03, 0x06, 0x00, 0x00];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(),
+               "type,description,value\nuint8_be,version,1\nuint8_be,data[0],2\nuint8_be,data[1],3\n\
+                sum16_le,sum,6\nvalid,sum valid,true\nxor8,check,0\nvalid,check valid,false\n");
+}
+
+#[test]
+fn test_decode_checksum_rows_round_trip() {
+    let mut crc = Template::new(FieldType::Checksum(ChecksumKind::Crc16Ccitt, Endianness::Big), "crc".to_string());
+    crc.span = Span::parse("data");
+
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "version".to_string()),
+                         Template::array(FieldType::u8(Endianness::Big), "data".to_string(), 2),
+                         crc);
+
+    // the reserved 'valid' rows written after checksums are skipped when the rows are encoded again
+    let bytes: &[u8] = &[0x01, 0x02, 0x03, 0x00, 0x00,
+                         0x01, 0x04, 0x05, 0x00, 0x00];
+    for values_mode in [ValuesMode::Converted, ValuesMode::Both] {
+        let mut input = bytes;
+        let mut rows: Vec<u8> = Vec::new();
+        decode(&mut input, &mut rows, &templates, true, values_mode, &Framing::default(), &PartialRecord::default()).unwrap();
+        assert!(String::from_utf8(rows.clone()).unwrap().contains("\nvalid,crc valid,false"));
+
+        let mut input: &[u8] = &rows;
+        let mut output: Vec<u8> = Vec::new();
+        encode(&mut input, &mut output, &templates, true, values_mode, &Framing::default()).unwrap();
+
+        let mut expected = Vec::new();
+        for record in bytes.chunks(5) {
+            let crc = ChecksumKind::Crc16Ccitt.compute(&record[1..3]) as u16;
+            expected.extend_from_slice(&record[..3]);
+            expected.extend_from_slice(&crc.to_be_bytes());
+        }
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn test_decode_sync_marker() {
+    let templates = vec!(Template::new(FieldType::Bcd(8, Endianness::Big, BitSize(8), BcdPacking::Packed), "value".to_string()),
+                         Template::new(FieldType::u8(Endianness::Big), "count".to_string()));
+    let framing = Framing { sync: Some(SyncMarker(vec!(0xEB, 0x90))), ..Default::default() };
+
+    // bytes between records are skipped, and a record that can not be read is
+    // skipped by looking for the next marker from just after its own, which
+    // here is found within it
+    let mut input: &[u8] = &[0x00, 0xEB, 0x90, 0x12, 0x01,
+                             0x55, 0xEB, 0x90, 0x1A, 0xEB,
+                             0x90, 0x34, 0x02,
+                             0xEB, 0x90, 0x56];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing, &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "value,count\n12,1\n34,2\n");
+
+    let mut input: &[u8] = &[0x12, 0x01];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &framing, &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "type,description,value\n");
+}
+
+#[test]
+fn test_decode_record_length() {
+    let mut rest = Template::new(FieldType::Blob(BlobFormat::Hex), "extra".to_string());
+    rest.record_length = Some(FieldRef::parse("packetLen+2"));
+
+    let mut templates = vec!(Template::new(FieldType::u8(Endianness::Big), "packetLen".to_string()),
+                             Template::new(FieldType::u8(Endianness::Big), "first".to_string()),
+                             rest);
+
+    // the bytes of each record after its fields are written out in hex
+    let mut input: &[u8] = &[0x00, 0x0A, 0x01, 0x0B, 0xCC, 0x00, 0x0C];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "packetLen,first,extra\n0,10,\n1,11,CC\n0,12,\n");
+
+    // or skipped without a description
+    templates[2].description = String::new();
+    let mut input: &[u8] = &[0x02, 0x0B, 0xCC, 0xDD];
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "packetLen,first\n2,11\n");
+
+    // a record can not be shorter than its fields
+    templates[2].record_length = Some(FieldRef::new("packetLen"));
+    let mut input: &[u8] = &[0x01, 0x0A];
+    let mut output: Vec<u8> = Vec::new();
+    assert!(decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default(), &PartialRecord::default()).is_none());
+}
+
+#[test]
+fn test_decode_framing() {
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "value".to_string()));
+
+    // the file header and the padding between records are skipped, with the
+    // padding after the last record cut short
+    let mut input: &[u8] = &[0xFF, 0xFF, 0xFF, 0x01, 0xAA, 0xAA, 0x02, 0xAA, 0xAA, 0x03, 0xAA];
+    let framing = Framing { skip: 3, stride: Some(3), ..Default::default() };
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing, &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "value\n1\n2\n3\n");
+
+    let mut input: &[u8] = &[0xFF, 0xFF, 0xFF, 0x01, 0xAA, 0xAA, 0x02, 0xAA, 0xAA, 0x03, 0xAA];
+    let framing = Framing { count: Some(2), ..framing };
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing, &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "value\n1\n2\n");
+
+    // with a sync marker, the stride starts at the marker
+    let mut input: &[u8] = &[0xEB, 0x90, 0x01, 0xAA, 0xEB, 0x90, 0x02, 0xAA];
+    let framing = Framing { sync: Some(SyncMarker(vec!(0xEB, 0x90))), stride: Some(4), ..Default::default() };
+    let mut output: Vec<u8> = Vec::new();
+    decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing, &PartialRecord::default()).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "value\n1\n2\n");
+
+    // records can not be longer than the stride
+    let templates = vec!(Template::new(FieldType::u16(Endianness::Big), "value".to_string()));
+    let mut input: &[u8] = &[0x00, 0x01];
+    let framing = Framing { stride: Some(1), ..Default::default() };
+    let mut output: Vec<u8> = Vec::new();
+    assert!(decode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing, &PartialRecord::default()).is_none());
+}
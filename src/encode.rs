@@ -1,18 +1,48 @@
 use std::fs::File;
-use std::io::{Write, Read};
+use std::io::{self, Write, Read};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::str::FromStr;
 
 use byteorder::WriteBytesExt;
 
 use crate::types::*;
+use crate::options::Framing;
+use crate::float::*;
 use crate::bit_buffer::*;
 use crate::template::*;
+use crate::layout::*;
+use crate::varint;
+use crate::bcd;
+use crate::time;
+use crate::calibration::*;
+use crate::checksum::{Span, record_checksum, valid_header, VALID_ROW_TYPE};
+#[cfg(test)]
+use crate::enumeration::*;
+#[cfg(test)]
+use crate::condition::Condition;
 
 
-pub fn encode<R: Read, W: Write>(input: &mut R, output: &mut W, templates: &Vec<Template>, rows: bool) -> Option<()> {
+pub fn encode<R: Read, W: Write>(input: &mut R,
+                                 output: &mut W,
+                                 templates: &[Template],
+                                 rows: bool,
+                                 values_mode: ValuesMode,
+                                 framing: &Framing) -> Option<()> {
     let mut lines = csv::Reader::from_reader(input);
 
+    // a file header before the first record is filled with zeros
+    io::copy(&mut io::repeat(0).take(framing.skip), output).ok()?;
+
+    // at most the given number of records are written out
+    let max_records = framing.count.unwrap_or(usize::MAX);
+
     let mut bit_buffer: BitBuffer = Default::default();
 
+    // arrays have a column for each element, and fields repeated by a count
+    // field have a single column
+    let columns: Vec<Element> = record_columns(templates);
+
     // if processing rows, each row contains a field
     trace!("Starting encoding");
     if rows {
@@ -25,130 +55,1317 @@ pub fn encode<R: Read, W: Write>(input: &mut R, output: &mut W, templates: &Vec<
             panic!("A row based csv file must at least have a field for value, description and type!");
         }
 
-        for record in lines.records() {
-            trace!("Processing record");
+        let mut records = lines.records().peekable();
 
-            let rec = record.ok()?;
+        // without a template, each row is encoded as it is, and the whole file
+        // is a single record
+        if columns.is_empty() {
+            let mut pending: Vec<Pending> = vec!();
 
-            let type_str = &rec[0];
-            let description = &rec[1];
-            let value_str = &rec[2];
+            for record in records {
+                trace!("Processing record");
 
-            let typ = type_str.parse().ok()?;
+                let rec = record.ok()?;
+                let (typ, value) = row_value(&rec, None, values_mode)?;
 
-            let field = Field { value: to_value(typ, value_str),
-                                typ: typ,
-                                description: description.to_string(),
-            };
-            trace!("{}", field);
+                pending.push(Pending { description: rec[1].to_string(), typ, value, span: None, record_length: None });
+            }
+
+            return write_pending(output, pending, &mut bit_buffer, framing);
+        }
+
+        // padding in the template is filled in between rows, so the rows are
+        // encoded as the template is laid out
+        let mut num_records = 0;
+        while records.peek().is_some() && num_records < max_records {
+            num_records += 1;
+
+            // whether the rows ended within this record
+            let mut rows_ended = false;
+
+            // the fields of the record, written out once their lengths are filled in
+            let mut pending: Vec<Pending> = vec!();
+
+            let result = walk_record(templates, &mut |element| {
+                let template = element.template;
+
+                // padding, constants, and checksums given explicitly in the row based file
+                // are encoded like any other row, otherwise they are filled in
+                let is_explicit_row = match records.peek() {
+                    Some(Ok(rec)) if template.constant.is_some() || template.typ.is_checksum() => rec[1] == element.description,
+                    Some(Ok(rec)) => Template::parse_type(&rec[0]).map(|(typ, _)| typ.is_pad()).unwrap_or(false),
+                    _ => false,
+                };
+
+                if (template.is_hidden() || template.typ.is_checksum()) && !is_explicit_row {
+                    return add_pending(&mut pending, element, template.typ, Some(hidden_value(template)?));
+                }
+
+                let rec = match records.next() {
+                    Some(record) => record.ok()?,
+
+                    None => {
+                        rows_ended = true;
+                        return None;
+                    },
+                };
+
+                trace!("Processing record");
+
+                // checksums are computed when the record is written out, replacing any value
+                // given, and a row flagging whether the checksum was valid when decoded is skipped
+                if template.typ.is_checksum() {
+                    if let Some(Ok(flag)) = records.peek() {
+                        if &flag[0] == VALID_ROW_TYPE && flag[1] == valid_header(&element.description) {
+                            records.next();
+                        }
+                    }
+
+                    return add_pending(&mut pending, element, template.typ, Some(hidden_value(template)?));
+                }
+
+                // the rows of a union must be the fields of the branch chosen by the record
+                if element.in_branch && rec[1] != element.description {
+                    error!("Row '{}' is not field {} of the union branch chosen for this record", &rec[1], element.description);
+                    return None;
+                }
+
+                // converted fields use the template of the field with the row's description
+                let row_template = if rec[1] == element.description { Some(template) } else { None };
+
+                let (typ, value) = row_value(&rec, row_template, values_mode)?;
 
-            write_out(output, &field, &mut bit_buffer);
+                if let (Some(constant), Some(value)) = (&template.constant, &value) {
+                    if value.to_string() != *constant {
+                        error!("Row '{}' gives {} for a constant field, rather than {}", &rec[1], value.to_string(), constant);
+                        return None;
+                    }
+                }
+
+                add_pending(&mut pending, element, typ, value)
+            });
+
+            if result.is_none() && !rows_ended {
+                return None;
+            }
+
+            write_pending(output, pending, &mut bit_buffer, framing)?;
         }
     } else { // if processing columns, each row contains all items in the template
         trace!("Column based");
-        for record in lines.records() {
+
+        for record in lines.records().take(max_records) {
             trace!("Processing record");
 
             let rec = record.ok()?;
 
-            for (value_str, template) in rec.iter().zip(templates) {
+            // the values of each column, split into a value for each repetition of repeated fields
+            let mut cells = rec.iter();
+            let mut values: HashMap<&str, VecDeque<String>> = HashMap::new();
+
+            for element in columns.iter().filter(|element| !element.template.is_hidden()) {
+                let cell = match cells.next() {
+                    Some(cell) => cell,
+                    None => break,
+                };
+
+                // when both raw and converted values are given, the raw value is used
+                // and the converted value is skipped
+                if values_mode == ValuesMode::Both && element.template.is_converted() {
+                    cells.next();
+                }
+
+                // checksums are followed by a column flagging whether they were valid, which is skipped
+                if element.template.typ.is_checksum() {
+                    cells.next();
+                }
+
+                let column_values = if !element.repeated {
+                    vec!(cell.to_string())
+                } else if cell.trim().is_empty() {
+                    vec!()
+                } else {
+                    split_repeated(cell)
+                };
+
+                values.insert(&element.column, column_values.into());
+            }
+
+            // whether the record ended before all of its columns were given
+            let mut missing_column = false;
+
+            // the columns of the fields present in the record
+            let mut visited: HashSet<String> = HashSet::new();
+
+            // the fields of the record, written out once their lengths are filled in
+            let mut pending: Vec<Pending> = vec!();
+
+            let result = walk_record(templates, &mut |element| {
                 trace!("Processing field");
 
-                let field = Field { value: to_value(template.typ, value_str),
-                                    typ: template.typ,
-                                    description: template.description.clone(),
+                let template = element.template;
+                visited.insert(element.column.clone());
+
+                // padding and constants have no column, and are filled in from the template
+                if template.is_hidden() {
+                    return add_pending(&mut pending, element, template.typ, Some(hidden_value(template)?));
+                }
+
+                let value_str = match values.get_mut(element.column.as_str()) {
+                    Some(column_values) => {
+                        match column_values.pop_front() {
+                            Some(value_str) => value_str,
+
+                            None => {
+                                error!("Column '{}' does not have a value for {}", element.column, element.description);
+                                return None;
+                            },
+                        }
+                    },
+
+                    None => {
+                        missing_column = true;
+                        return None;
+                    },
+                };
+
+                // checksums are computed when the record is written out, replacing any value given
+                if template.typ.is_checksum() {
+                    return add_pending(&mut pending, element, template.typ, Some(hidden_value(template)?));
+                }
+
+                // blank values, such as the length of a blob, are left to be filled in
+                if is_blank(template.typ, &value_str) {
+                    return add_pending(&mut pending, element, template.typ, None);
+                }
+
+                let value_str = match values_mode {
+                    ValuesMode::Converted => unconvert_value(&value_str, template.typ, &element.description, Some(template))?,
+                    ValuesMode::Both | ValuesMode::Raw => value_str,
                 };
-                trace!("{}", field);
 
-                write_out(output, &field, &mut bit_buffer);
+                add_pending(&mut pending, element, template.typ, Some(to_value(template.typ, &value_str)?))
+            });
+
+            if result.is_none() && !missing_column {
+                return None;
+            }
+
+            // repeated columns must have a value for each repetition and no more, and
+            // the columns of union branches not chosen must be blank, while the columns
+            // of other absent fields are left over and ignored, unless the record is short
+            for (column, column_values) in values.iter().filter(|(_, column_values)| !missing_column && !column_values.is_empty()) {
+                let element = columns.iter().find(|element| element.column == *column);
+                let repeated = element.map(|element| element.repeated).unwrap_or(false);
+                let in_branch = element.map(|element| element.in_branch).unwrap_or(false);
+
+                let given = column_values.iter().any(|value| !value.trim().is_empty());
+
+                if in_branch && given && !visited.contains(*column) {
+                    error!("Column '{}' is given, but is not in the union branch chosen for this record", column);
+                    return None;
+                }
+
+                if repeated {
+                    error!("Column '{}' has more values than its count field gives", column);
+                    return None;
+                }
+
+                if given {
+                    warn!("Column '{}' is given for a field that is not present", column);
+                }
             }
+
+            write_pending(output, pending, &mut bit_buffer, framing)?;
         }
     }
 
     Some(())
 }
 
-fn to_value(typ: FieldType, value_str: &str) -> Value {
+/// A field waiting to be written out at the end of its record. Its value
+/// may be left out, to be filled in with the length of a later blob. The
+/// rest of a record has the record's length, which it is filled out to.
+struct Pending {
+    description: String,
+    typ: FieldType,
+    value: Option<Value>,
+    span: Option<Span>,
+    record_length: Option<usize>,
+}
+
+/// Add a field to the fields waiting to be written out, filling in the length
+/// field of a blob, and returning the value of the field for laying out the record.
+fn add_pending(pending: &mut Vec<Pending>, element: &Element, typ: FieldType, value: Option<Value>) -> Option<Value> {
+    if let (Some(length_field), Some(field_ref), Some(Value::Str(text)), FieldType::Blob(format)) =
+           (&element.length_field, &element.template.length_field, &value, typ) {
+        let length = format.parse(text)?.len() as i128 - field_ref.adjustment;
+
+        let target = match pending.iter_mut().find(|field| &field.description == length_field) {
+            Some(target) => target,
+
+            None => {
+                error!("Length field {} of blob {} is not in the same record", length_field, element.description);
+                return None;
+            },
+        };
+
+        let fits = match target.typ {
+            FieldType::Int(num_bits, _, _) => num_bits >= 128 || (length >> (num_bits - 1)) == 0,
+            FieldType::Uint(num_bits, _, _) => num_bits >= 127 || (length >> num_bits) == 0,
+            FieldType::Varint(_) => true,
+            _ => false,
+        };
+
+        if length < 0 || !fits {
+            error!("Blob {} gives length {}, which can not be written to length field {} of type {}",
+                   element.description, length, length_field, target.typ);
+            return None;
+        }
+
+        // lengths are filled in from the blob, even when they are given
+        if let Some(given) = target.value.as_ref().and_then(|value| value.as_integer()) {
+            if given != length {
+                warn!("Length field {} is {}, but blob {} gives {}", length_field, given, element.description, length);
+            }
+        }
+
+        target.value = Some(to_value(target.typ, &length.to_string())?);
+    }
+
+    // blank values stand in as a string, so they can not be used as a count
+    let layout_value = value.clone().unwrap_or_else(|| Value::Str(String::new()));
+
+    let record_length = element.template.record_length.as_ref().and(element.length);
+
+    pending.push(Pending { description: element.description.clone(), typ, value, span: element.template.span.clone(), record_length });
+
+    Some(layout_value)
+}
+
+/// Write out the fields of a record, once their lengths have been filled in,
+/// filling in any checksums from the bytes of the record.
+fn write_pending<W>(writer: &mut W, pending: Vec<Pending>, bit_buffer: &mut BitBuffer, framing: &Framing) -> Option<()>
+    where W: WriteBytesExt {
+
+    // the record is written to a buffer first, keeping the bytes each field was written to
+    let mut record: Vec<u8> = vec!();
+    let mut field_ranges: Vec<(String, Range<usize>)> = vec!();
+    let mut checksums: Vec<(String, FieldType, Option<Span>, Range<usize>)> = vec!();
+
+    for Pending { description, typ, value, span, record_length } in pending {
+        let value = match value {
+            Some(value) => value,
+
+            None => {
+                error!("Field {} has no value", description);
+                return None;
+            },
+        };
+
+        let field = Field { value, typ, description };
+        trace!("{}", field);
+
+        let start = record.len();
+        write_out(&mut record, &field, bit_buffer)?;
+        field_ranges.push((field.description.clone(), start..record.len()));
+
+        // the rest of a record is filled with zeros up to the record's length
+        if let Some(record_length) = record_length {
+            if record.len() > record_length {
+                error!("Record is {} bytes up to the end of {}, which is longer than its length of {}",
+                       record.len(), field.description, record_length);
+                return None;
+            }
+
+            record.resize(record_length, 0);
+        }
+
+        if typ.is_checksum() {
+            checksums.push((field.description, typ, span, start..record.len()));
+        }
+    }
+
+    // checksums are filled in order, so a later checksum may cover an earlier one
+    for (description, typ, span, range) in checksums {
+        let (kind, endianness) = match typ {
+            FieldType::Checksum(kind, endianness) => (kind, endianness),
+            _ => unreachable!(),
+        };
+
+        if range.len() * 8 != kind.num_bits() {
+            error!("Checksum {} must be whole bytes, and not part of a bit field", description);
+            return None;
+        }
+
+        let checksum = record_checksum(kind, &span, &record, &field_ranges, &range)?;
+
+        let bytes = match endianness {
+            Endianness::Big => checksum.to_be_bytes()[16 - range.len()..].to_vec(),
+            Endianness::Little => checksum.to_le_bytes()[..range.len()].to_vec(),
+        };
+
+        record[range].copy_from_slice(&bytes);
+    }
+
+    // with a sync marker, each record follows its own marker
+    let marker: &[u8] = match &framing.sync {
+        Some(SyncMarker(marker)) => marker,
+        None => &[],
+    };
+
+    // records shorter than the stride are padded with zeros up to the next record
+    if let Some(stride) = framing.stride {
+        if marker.len() + record.len() > stride {
+            error!("Record is {} bytes, which is longer than the stride of {} bytes", marker.len() + record.len(), stride);
+            return None;
+        }
+
+        record.resize(stride - marker.len(), 0);
+    }
+
+    // each record is flushed once written, so streams are written out as they are encoded
+    writer.write_all(marker).ok()?;
+    writer.write_all(&record).ok()?;
+    writer.flush().ok()
+}
+
+/// The value written out for a field left out of the input, which is either its
+/// constant, its fill value for padding, zero for a checksum until it is computed,
+/// or nothing for the rest of a record, which is filled with zeros.
+fn hidden_value(template: &Template) -> Option<Value> {
+    match &template.constant {
+        Some(constant) => to_value(template.typ, constant),
+        None if template.record_length.is_some() => Some(Value::Str(String::new())),
+        None => to_value(template.typ, &template.fill.to_string()),
+    }
+}
+
+/// Whether a value is left blank, to be filled in. Strings and blobs may be empty.
+fn is_blank(typ: FieldType, value_str: &str) -> bool {
+    value_str.trim().is_empty() && !matches!(typ, FieldType::Str(_, _, _) | FieldType::Blob(_))
+}
+
+/// The type and raw value of a row of a row based file, converting its value
+/// with the given template. Blank values are left to be filled in.
+fn row_value(rec: &csv::StringRecord, template: Option<&Template>, values_mode: ValuesMode) -> Option<(FieldType, Option<Value>)> {
+    let type_str = &rec[0];
+    let description = &rec[1];
+    let value_str = &rec[2];
+
+    let typ = match Template::parse_type(type_str).ok()? {
+        (typ, None) => typ,
+
+        (_, Some(_)) => {
+            error!("Array type '{}' must be given as a row for each element in a row based file", type_str);
+            return None;
+        },
+    };
+
+    if is_blank(typ, value_str) {
+        return Some((typ, None));
+    }
+
+    // when both raw and converted values are given, the raw value is used
+    let value_str = if values_mode == ValuesMode::Converted {
+        unconvert_value(value_str, typ, description, template)?
+    } else {
+        value_str.to_string()
+    };
+
+    Some((typ, Some(to_value(typ, &value_str)?)))
+}
+
+/// Convert the converted value of a field back to its raw value. Enumerated fields
+/// may be given as labels or numbers, calibrated fields are given in engineering
+/// units, timestamps are given as ISO 8601 times, and fields with no conversion
+/// are left as they are.
+fn unconvert_value(value_str: &str, typ: FieldType, description: &str, template: Option<&Template>) -> Option<String> {
+    if let Some(enumeration) = template.and_then(|template| template.enumeration.as_ref()) {
+        let value = enumeration.value(value_str);
+
+        if value.is_none() {
+            error!("'{}' is not a label or number for field {}", value_str.trim(), description);
+        }
+
+        return value;
+    }
+
+    if let Some(calibration) = template.and_then(|template| template.calibration.as_ref()) {
+        let value = uncalibrate(calibration, typ, value_str);
+
+        if value.is_none() {
+            error!("Could not convert '{}' to a raw value for field {}", value_str.trim(), description);
+        }
+
+        return value;
+    }
+
+    if let Some(scale) = template.and_then(|template| template.timestamp.as_ref()) {
+        let value = untime(scale, typ, value_str);
+
+        if value.is_none() {
+            error!("Could not convert '{}' to a timestamp for field {}", value_str.trim(), description);
+        }
+
+        return value;
+    }
+
+    Some(value_str.to_string())
+}
+
+/// Convert an ISO 8601 time back to the text of a raw count of time for the
+/// given field type, checking that the count fits in the field.
+fn untime(scale: &TimeScale, typ: FieldType, value_str: &str) -> Option<String> {
+    let picoseconds = time::parse_iso8601(value_str)?;
+
+    match typ {
+        FieldType::Int(_, _, _) | FieldType::Uint(_, _, _) => {
+            let counts = scale.counts(picoseconds);
+
+            let fits = match typ {
+                FieldType::Int(num_bits, _, _) => num_bits >= 128 || (counts >> (num_bits - 1)) == 0 || (counts >> (num_bits - 1)) == -1,
+                FieldType::Uint(num_bits, _, _) => counts >= 0 && (num_bits >= 127 || (counts >> num_bits) == 0),
+                _ => true,
+            };
+
+            if !fits {
+                error!("Time {} is {} counts from its epoch, which does not fit in a {} field", value_str.trim(), counts, typ);
+                return None;
+            }
+
+            Some(counts.to_string())
+        },
+
+        _ => Some(scale.counts_float(picoseconds).to_string()),
+    }
+}
+
+fn to_value(typ: FieldType, value_str: &str) -> Option<Value> {
   let value_str = value_str.trim();
 
-  match typ {
+  let value = match typ {
     FieldType::Int(num_bits, _, _) => {
         if num_bits <= 8 {
-            Value::Int8(value_str.parse().ok().unwrap())
+            Value::Int8(parse_number(value_str, typ)?)
         } else if num_bits <= 16 {
-            Value::Int16(value_str.parse().ok().unwrap())
+            Value::Int16(parse_number(value_str, typ)?)
         } else if num_bits <= 32 {
-            Value::Int32(value_str.parse().ok().unwrap())
+            Value::Int32(parse_number(value_str, typ)?)
         } else if num_bits <= 64 {
-            Value::Int64(value_str.parse().ok().unwrap())
+            Value::Int64(parse_number(value_str, typ)?)
+        } else if num_bits <= 128 {
+            Value::Int128(parse_number(value_str, typ)?)
         } else {
             panic!("{} bit fields are not allowed!", num_bits);
         }
     },
 
-    FieldType::Uint(num_bits, _, _) => {
+    FieldType::Uint(num_bits, _, _) | FieldType::Pad(num_bits, _, _) => {
         if num_bits <= 8 {
-            Value::Uint8(value_str.parse().ok().unwrap())
+            Value::Uint8(parse_number(value_str, typ)?)
         } else if num_bits <= 16 {
-            Value::Uint16(value_str.parse().ok().unwrap())
+            Value::Uint16(parse_number(value_str, typ)?)
         } else if num_bits <= 32 {
-            Value::Uint32(value_str.parse().ok().unwrap())
+            Value::Uint32(parse_number(value_str, typ)?)
         } else if num_bits <= 64 {
-            Value::Uint64(value_str.parse().ok().unwrap())
+            Value::Uint64(parse_number(value_str, typ)?)
+        } else if num_bits <= 128 {
+            Value::Uint128(parse_number(value_str, typ)?)
         } else {
             panic!("{} bit fields are not allowed!", num_bits);
         }
     },
 
+    // checksums hold an unsigned integer of their size
+    FieldType::Checksum(kind, endianness) => {
+        return to_value(FieldType::Uint(kind.num_bits(), endianness, BitSize(kind.num_bits())), value_str);
+    },
+
     FieldType::Float(_) => {
-        Value::Float(value_str.parse().ok().unwrap())
+        Value::Float(parse_number(value_str, typ)?)
     },
 
     FieldType::Double(_) => {
-        Value::Double(value_str.parse().ok().unwrap())
+        Value::Double(parse_number(value_str, typ)?)
+    },
+
+    // small floats are rounded to the nearest value they can represent
+    FieldType::Half(_) => {
+        Value::Half(half_from_bits(half_to_bits(parse_number(value_str, typ)?)))
+    },
+
+    FieldType::BFloat16(_) => {
+        Value::BFloat16(bfloat16_from_bits(bfloat16_to_bits(parse_number(value_str, typ)?)))
     },
-  }
+
+    FieldType::Bool(_, _, _) => {
+        match value_str.to_lowercase().as_str() {
+            "true" | "1" | "yes" => Value::Bool(true),
+            "false" | "0" | "no" => Value::Bool(false),
+            _ => {
+                error!("'{}' is not a boolean value", value_str);
+                return None;
+            },
+        }
+    },
+
+    FieldType::Bcd(num_bits, _, _, packing) => {
+        let value = parse_number(value_str, typ)?;
+
+        if bcd::to_bcd(value, num_bits, packing).is_none() {
+            error!("'{}' has more digits than its {} digit field", value_str, bcd::num_digits(num_bits, packing));
+            return None;
+        }
+
+        Value::Uint128(value)
+    },
+
+    FieldType::Str(num_bytes, _, encoding) => {
+        let bytes = match encoding.encode(value_str) {
+            Some(bytes) => bytes,
+
+            None => {
+                error!("'{}' can not be encoded as {}", value_str, encoding);
+                return None;
+            },
+        };
+
+        if bytes.len() > num_bytes {
+            error!("'{}' is longer than its {} byte field", value_str, num_bytes);
+            return None;
+        }
+
+        Value::Str(value_str.to_string())
+    },
+
+    FieldType::Time(code, epoch) => {
+        let picoseconds = match time::parse_iso8601(value_str) {
+            Some(picoseconds) => picoseconds,
+
+            None => {
+                error!("'{}' is not an ISO 8601 time", value_str);
+                return None;
+            },
+        };
+
+        if code.encode(epoch, picoseconds).is_none() {
+            error!("'{}' can not be encoded as a {} time", value_str, typ);
+            return None;
+        }
+
+        Value::Time(Timestamp { picoseconds, digits: code.digits() })
+    },
+
+    FieldType::Blob(format) => {
+        if format.parse(value_str).is_none() {
+            error!("'{}' is not valid {}", value_str, format);
+            return None;
+        }
+
+        Value::Str(value_str.to_string())
+    },
+
+    FieldType::Struct => {
+        panic!("A structure is encoded as its fields, and has no value of its own!");
+    },
+
+    FieldType::Varint(VarintEncoding::ULeb128) => {
+        Value::Uint128(parse_number(value_str, typ)?)
+    },
+
+    FieldType::Varint(_) => {
+        Value::Int128(parse_number(value_str, typ)?)
+    },
+  };
+
+  Some(value)
 }
 
-fn to_field(typ: FieldType, value_str: &str, description: String) -> Field {
-    Field {
-        value: to_value(typ, value_str),
+/// Parse a number from a csv entry, reporting an entry that is not a number of the field's type.
+fn parse_number<T: FromStr>(value_str: &str, typ: FieldType) -> Option<T> {
+    match value_str.parse() {
+        Ok(number) => Some(number),
+
+        Err(_) => {
+            error!("'{}' is not a valid {} value", value_str, typ);
+            None
+        },
+    }
+}
+
+fn to_field(typ: FieldType, value_str: &str, description: String) -> Option<Field> {
+    Some(Field {
+        value: to_value(typ, value_str)?,
         typ: typ,
         description: description,
-    }
+    })
 }
 
-fn write_out<W>(writer: &mut W, field: &Field, bit_buffer: &mut BitBuffer)
+fn write_out<W>(writer: &mut W, field: &Field, bit_buffer: &mut BitBuffer) -> Option<()>
     where W: WriteBytesExt {
 
-    bit_buffer.push_value(field.value, field.typ.num_bits(), field.typ.endianness());
+    // strings are written out directly, padded to the size of their field, outside of any bit field
+    if let (Value::Str(text), FieldType::Str(num_bytes, padding, encoding)) = (&field.value, field.typ) {
+        if !bit_buffer.is_empty() {
+            error!("String field {} can not be placed within a bit field", field.description);
+            return None;
+        }
+
+        let mut bytes = encoding.encode(text).unwrap();
+        bytes.resize(num_bytes, padding.byte());
+        writer.write_all(&bytes).unwrap();
+        return Some(());
+    }
+
+    // blobs are written out directly, with their length given by another field, outside of any bit field
+    if let (Value::Str(text), FieldType::Blob(format)) = (&field.value, field.typ) {
+        if !bit_buffer.is_empty() {
+            error!("Blob field {} can not be placed within a bit field", field.description);
+            return None;
+        }
+
+        writer.write_all(&format.parse(text).unwrap()).unwrap();
+        return Some(());
+    }
+
+    // time codes are written out directly as bytes, outside of any bit field
+    if let (Value::Time(timestamp), FieldType::Time(code, epoch)) = (&field.value, field.typ) {
+        if !bit_buffer.is_empty() {
+            error!("Time field {} can not be placed within a bit field", field.description);
+            return None;
+        }
+
+        writer.write_all(&code.encode(epoch, timestamp.picoseconds).unwrap()).unwrap();
+        return Some(());
+    }
+
+    // varints are written out directly, using as many bytes as their value needs, outside of any bit field
+    if let FieldType::Varint(encoding) = field.typ {
+        if !bit_buffer.is_empty() {
+            error!("Varint field {} can not be placed within a bit field", field.description);
+            return None;
+        }
 
-    for byte in bit_buffer {
-        writer.write(&[byte]).unwrap();
+        let bytes = match (encoding, &field.value) {
+            (VarintEncoding::ULeb128, Value::Uint128(value)) => varint::write_unsigned(*value),
+            (VarintEncoding::SLeb128, Value::Int128(value)) => varint::write_signed(*value),
+            (VarintEncoding::ZigZag, Value::Int128(value)) => varint::write_zigzag(*value),
+            (_, value) => panic!("Value {} can not be written as a {}!", value.to_string(), encoding),
+        };
+        writer.write_all(&bytes).unwrap();
+        return Some(());
     }
+
+    // decimals are converted to their digits before being packed into the bit buffer
+    let value = match (field.typ, &field.value) {
+        (FieldType::Bcd(num_bits, _, _, packing), Value::Uint128(value)) => {
+            Value::Uint128(bcd::to_bcd(*value, num_bits, packing).unwrap())
+        },
+
+        (_, value) => value.clone(),
+    };
+
+    bit_buffer.push_value(value, field.typ.num_bits(), field.typ.endianness());
+
+    match field.typ.endianness() {
+        Endianness::Big => {
+            for byte in bit_buffer {
+                writer.write(&[byte]).unwrap();
+            }
+        },
+
+        // little endian bit fields start with their least significant byte, so
+        // they are written out once the whole bit field is filled in
+        Endianness::Little => {
+            if bit_buffer.bits_avail as NumBits >= field.typ.bit_size().num_bits() {
+                while let Some(byte) = bit_buffer.pull_byte_le() {
+                    writer.write_all(&[byte]).unwrap();
+                }
+            }
+        },
+    }
+
+    Some(())
 }
 
 #[test]
 fn test_encode_to_value() {
-    assert_eq!(to_value(FieldType::u8(Endianness::Big), "1"), Value::Uint8(1));
-    assert_eq!(to_value(FieldType::u16(Endianness::Big), "1"), Value::Uint16(1));
-    assert_eq!(to_value(FieldType::u32(Endianness::Big), "1"), Value::Uint32(1));
-    assert_eq!(to_value(FieldType::u64(Endianness::Big), "1"), Value::Uint64(1));
-    assert_eq!(to_value(FieldType::i8(Endianness::Big), "1"), Value::Int8(1));
-    assert_eq!(to_value(FieldType::i16(Endianness::Big), "1"), Value::Int16(1));
-    assert_eq!(to_value(FieldType::i32(Endianness::Big), "1"), Value::Int32(1));
-    assert_eq!(to_value(FieldType::i64(Endianness::Big), "1"), Value::Int64(1));
-    assert_eq!(to_value(FieldType::float(Endianness::Big), "1.0"), Value::Float(1.0));
-    assert_eq!(to_value(FieldType::double(Endianness::Big), "1.0"), Value::Double(1.0));
-
-    assert_eq!(to_value(FieldType::u8(Endianness::Little), "1"), Value::Uint8(1));
-    assert_eq!(to_value(FieldType::u16(Endianness::Little), "1"), Value::Uint16(1));
-    assert_eq!(to_value(FieldType::u32(Endianness::Little), "1"), Value::Uint32(1));
-    assert_eq!(to_value(FieldType::u64(Endianness::Little), "1"), Value::Uint64(1));
-    assert_eq!(to_value(FieldType::i8(Endianness::Little), "1"), Value::Int8(1));
-    assert_eq!(to_value(FieldType::i16(Endianness::Little), "1"), Value::Int16(1));
-    assert_eq!(to_value(FieldType::i32(Endianness::Little), "1"), Value::Int32(1));
-    assert_eq!(to_value(FieldType::i64(Endianness::Little), "1"), Value::Int64(1));
-    assert_eq!(to_value(FieldType::float(Endianness::Little), "1.0"), Value::Float(1.0));
-    assert_eq!(to_value(FieldType::double(Endianness::Little), "1.0"), Value::Double(1.0));
-} 
+    assert_eq!(to_value(FieldType::u8(Endianness::Big), "1"), Some(Value::Uint8(1)));
+    assert_eq!(to_value(FieldType::u16(Endianness::Big), "1"), Some(Value::Uint16(1)));
+    assert_eq!(to_value(FieldType::u32(Endianness::Big), "1"), Some(Value::Uint32(1)));
+    assert_eq!(to_value(FieldType::u64(Endianness::Big), "1"), Some(Value::Uint64(1)));
+    assert_eq!(to_value(FieldType::i8(Endianness::Big), "1"), Some(Value::Int8(1)));
+    assert_eq!(to_value(FieldType::i16(Endianness::Big), "1"), Some(Value::Int16(1)));
+    assert_eq!(to_value(FieldType::i32(Endianness::Big), "1"), Some(Value::Int32(1)));
+    assert_eq!(to_value(FieldType::i64(Endianness::Big), "1"), Some(Value::Int64(1)));
+    assert_eq!(to_value(FieldType::float(Endianness::Big), "1.0"), Some(Value::Float(1.0)));
+    assert_eq!(to_value(FieldType::double(Endianness::Big), "1.0"), Some(Value::Double(1.0)));
+
+    assert_eq!(to_value(FieldType::u8(Endianness::Little), "1"), Some(Value::Uint8(1)));
+    assert_eq!(to_value(FieldType::u16(Endianness::Little), "1"), Some(Value::Uint16(1)));
+    assert_eq!(to_value(FieldType::u32(Endianness::Little), "1"), Some(Value::Uint32(1)));
+    assert_eq!(to_value(FieldType::u64(Endianness::Little), "1"), Some(Value::Uint64(1)));
+    assert_eq!(to_value(FieldType::i8(Endianness::Little), "1"), Some(Value::Int8(1)));
+    assert_eq!(to_value(FieldType::i16(Endianness::Little), "1"), Some(Value::Int16(1)));
+    assert_eq!(to_value(FieldType::i32(Endianness::Little), "1"), Some(Value::Int32(1)));
+    assert_eq!(to_value(FieldType::i64(Endianness::Little), "1"), Some(Value::Int64(1)));
+    assert_eq!(to_value(FieldType::float(Endianness::Little), "1.0"), Some(Value::Float(1.0)));
+    assert_eq!(to_value(FieldType::double(Endianness::Little), "1.0"), Some(Value::Double(1.0)));
+}
+
+#[test]
+fn test_encode_to_value_string() {
+    assert_eq!(to_value(FieldType::string(4), " abc "), Some(Value::Str("abc".to_string())));
+}
+
+#[test]
+fn test_encode_to_value_string_too_long() {
+    assert_eq!(to_value(FieldType::string(2), "abc"), None);
+}
+
+#[test]
+fn test_encode_to_value_string_not_ascii() {
+    assert_eq!(to_value(FieldType::string(4), "caf\u{e9}"), None);
+}
+
+#[test]
+fn test_encode_string_padding() {
+    let mut input: &[u8] = "type,description,value\nstring6_space,a,abc\nchar[4],b,xy\nstring4_latin1,c,\u{e9}\n".as_bytes();
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    assert_eq!(output, b"abc   xy\0\0\xe9\0\0\0".to_vec());
+
+    // strings can not be placed within a bit field
+    let mut input: &[u8] = b"type,description,value\nuint4_be,n,1\nchar[2],s,ab\nuint4_be,m,2\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).is_none());
+
+    // or be longer than their field
+    let mut input: &[u8] = b"type,description,value\nchar[2],s,abc\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_array_columns() {
+    let mut input: &[u8] = b"samples[0],samples[1],nibbles[0],nibbles[1],nibbles[2],nibbles[3]\n1,2,1,2,3,4\n";
+
+    let endianness = Endianness::Big;
+    let templates = vec!(Template::array(FieldType::u16(endianness), "samples".to_string(), 2),
+                         Template::array(FieldType::Uint(4, endianness, BitSize(16)), "nibbles".to_string(), 4));
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    assert_eq!(output, vec!(0x00, 0x01, 0x00, 0x02, 0x12, 0x34));
+}
+
+#[test]
+fn test_encode_fills_padding() {
+    let endianness = Endianness::Big;
+
+    let mut spare = Template::array(FieldType::Pad(8, endianness, BitSize(8)), "spare".to_string(), 2);
+    spare.fill = 0xFF;
+
+    let templates = vec!(Template::new(FieldType::u8(endianness), "a".to_string()),
+                         spare,
+                         Template::new(FieldType::Pad(4, endianness, BitSize(8)), "reserved".to_string()),
+                         Template::new(FieldType::Uint(4, endianness, BitSize(8)), "b".to_string()),
+                         Template::new(FieldType::Pad(8, endianness, BitSize(8)), "end".to_string()));
+
+    let mut input: &[u8] = b"a,b\n1,2\n3,4\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x01, 0xFF, 0xFF, 0x02, 0x00, 0x03, 0xFF, 0xFF, 0x04, 0x00));
+
+    let mut input: &[u8] = b"type,description,value\nuint8_be,a,1\nuint4_be:8,b,2\nuint8_be,a,3\nuint4_be:8,b,4\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x01, 0xFF, 0xFF, 0x02, 0x00, 0x03, 0xFF, 0xFF, 0x04, 0x00));
+}
+
+#[test]
+fn test_encode_bit_sizes() {
+    let mut input: &[u8] =
+        b"type,description,value\nuint16_le,a,258\nuint24_le,b,197121\nint48_be,c,-2\nuint4_le:8,d,1\nuint4_le:8,e,2\nuint12_be:24,f,1\nuint12_be:24,g,2\n";
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    assert_eq!(output, vec!(0x02, 0x01,
+                            0x01, 0x02, 0x03,
+                            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFE,
+                            0x21,
+                            0x00, 0x10, 0x02));
+}
+
+#[test]
+fn test_encode_to_value_128_bits() {
+    assert_eq!(to_value(FieldType::u128(Endianness::Little), "1"), Some(Value::Uint128(1)));
+    assert_eq!(to_value(FieldType::i128(Endianness::Little), "-1"), Some(Value::Int128(-1)));
+}
+
+#[test]
+fn test_encode_128_bits() {
+    let mut input: &[u8] =
+        b"type,description,value\nuint128_be,a,1\nint128_le,b,-2\nuint32_le:96,c,1\nuint64_le:96,d,2\n";
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    let mut expected = vec!(0; 15);
+    expected.push(1);
+    expected.push(0xFE);
+    expected.extend(vec!(0xFF; 15));
+    expected.extend(vec!(1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0));
+
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_encode_to_value_small_floats() {
+    assert_eq!(to_value(FieldType::half(Endianness::Big), "0.1"), Some(Value::Half(0.099975586)));
+    assert_eq!(to_value(FieldType::half(Endianness::Big), "-inf"), Some(Value::Half(f32::NEG_INFINITY)));
+    assert_eq!(to_value(FieldType::half(Endianness::Big), "Infinity"), Some(Value::Half(f32::INFINITY)));
+    assert_eq!(to_value(FieldType::bfloat16(Endianness::Big), "3.14159"), Some(Value::BFloat16(3.140625)));
+    match to_value(FieldType::bfloat16(Endianness::Big), "NaN") {
+        Some(Value::BFloat16(val)) => assert!(val.is_nan()),
+        value => panic!("Unexpected value {:?}", value),
+    }
+}
+
+#[test]
+fn test_encode_small_floats() {
+    let mut input: &[u8] = b"type,description,value\nhalf_be,a,1.0\nhalf_le,b,-2\nbfloat16_be,c,inf\nbfloat16_le,d,1\n";
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    assert_eq!(output, vec!(0x3C, 0x00, 0x00, 0xC0, 0x7F, 0x80, 0x80, 0x3F));
+}
+
+#[test]
+fn test_encode_to_value_bool() {
+    assert_eq!(to_value(FieldType::bool(Endianness::Big), "TRUE"), Some(Value::Bool(true)));
+    assert_eq!(to_value(FieldType::bool(Endianness::Big), " yes"), Some(Value::Bool(true)));
+    assert_eq!(to_value(FieldType::bool(Endianness::Big), "1"), Some(Value::Bool(true)));
+    assert_eq!(to_value(FieldType::bool(Endianness::Big), "False"), Some(Value::Bool(false)));
+    assert_eq!(to_value(FieldType::bool(Endianness::Big), "no"), Some(Value::Bool(false)));
+    assert_eq!(to_value(FieldType::bool(Endianness::Big), "0"), Some(Value::Bool(false)));
+}
+
+#[test]
+fn test_encode_to_value_bool_invalid() {
+    assert_eq!(to_value(FieldType::bool(Endianness::Big), "2"), None);
+    assert_eq!(to_value(FieldType::bool(Endianness::Big), "maybe"), None);
+}
+
+#[test]
+fn test_encode_bool() {
+    let mut input: &[u8] = b"type,description,value\nbool_be,a,true\nflag1_be:8,b,yes\nbool7_be:8,c,0\nbool4_le:8,d,1\nbool4_le:8,e,no\n";
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    assert_eq!(output, vec!(0x01, 0x80, 0x01));
+}
+
+#[test]
+fn test_encode_enumeration() {
+    let endianness = Endianness::Big;
+
+    let mut mode = Template::new(FieldType::u8(endianness), "mode".to_string());
+    mode.enumeration = Enumeration::parse("0=IDLE;1=SAFE;2=SCIENCE");
+
+    let templates = vec!(mode, Template::new(FieldType::u8(endianness), "count".to_string()));
+
+    let mut input: &[u8] = b"mode,count\nSCIENCE,3\n1,4\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x02, 0x03, 0x01, 0x04));
+
+    let mut input: &[u8] = b"type,description,value\nuint8_be,mode,SAFE\nuint8_be,count,5\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x01, 0x05));
+
+    let mut input: &[u8] = b"mode,count\nBOOT,3\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_calibration() {
+    let endianness = Endianness::Big;
+
+    let mut volts = Template::new(FieldType::u8(endianness), "volts".to_string());
+    volts.calibration = Some(Calibration::linear(0.5, -1.0));
+
+    let mut temperature = Template::new(FieldType::u16(endianness), "temperature".to_string());
+    temperature.calibration = Calibration::parse_polynomial("-40;0.5;0.001");
+
+    let templates = vec!(volts, temperature);
+
+    let mut input: &[u8] = b"volts,temperature\n1.5,-39.5\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x05, 0x00, 0x01));
+
+    let mut input: &[u8] = b"volts,temperature\n5,1\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Raw, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x05, 0x00, 0x01));
+
+    let mut input: &[u8] = b"volts,volts (V),temperature,temperature (C)\n5,1.5,1,-39.5\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Both, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x05, 0x00, 0x01));
+
+    let mut input: &[u8] = b"type,description,value\nuint8_be,volts,1.5\nuint16_be,temperature,-39.5\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x05, 0x00, 0x01));
+
+    // values outside of the raw field's range are an error
+    let mut input: &[u8] = b"volts,temperature\n200,0\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_varints() {
+    let mut input: &[u8] = b"type,description,value\nuint8_be,a,1\nuleb128,b,300\nsleb128,c,-1\nzigzag,d,64\n";
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    assert_eq!(output, vec!(0x01, 0xAC, 0x02, 0x7F, 0x80, 0x01));
+
+    // varints can not be placed within a bit field
+    let mut input: &[u8] = b"type,description,value\nuint4_be,a,1\nuleb128,b,300\nuint4_be,c,2\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_bcd() {
+    let mut input: &[u8] = b"type,description,value\nbcd16_be,year,2024\nbcd4_be:8,tens,5\nbcd4_be:8,ones,9\nubcd16_le,day,31\n";
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    assert_eq!(output, vec!(0x20, 0x24, 0x59, 0x01, 0x03));
+}
+
+#[test]
+fn test_encode_to_value_bcd_too_many_digits() {
+    assert_eq!(to_value(FieldType::Bcd(8, Endianness::Big, BitSize(8), BcdPacking::Packed), "100"), None);
+}
+
+#[test]
+fn test_encode_time_codes() {
+    let mut input: &[u8] = b"type,description,value\ncuc4.2,cuc,1958-01-01T00:01:00.25Z\ncds2@1970-01-01,cds,1970-01-02 00:00:01\n";
+
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    assert_eq!(output, vec!(0x00, 0x00, 0x00, 0x3C, 0x40, 0x00,
+                            0x00, 0x01, 0x00, 0x00, 0x03, 0xE8));
+
+    // time codes can not be placed within a bit field
+    let mut input: &[u8] = b"type,description,value\nuint4_be,a,1\ncuc4.2,cuc,1958-01-01T00:01:00.25Z\nuint4_be,b,2\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_to_value_time_before_epoch() {
+    assert_eq!(to_value(FieldType::Time(TimeCode::Cuc(4, 0), CCSDS_EPOCH), "1957-12-31T23:59:59Z"), None);
+}
+
+#[test]
+fn test_encode_to_value_time_not_iso8601() {
+    assert_eq!(to_value(FieldType::Time(TimeCode::Cuc(4, 0), CCSDS_EPOCH), "yesterday"), None);
+}
+
+#[test]
+fn test_encode_timestamps() {
+    let mut seconds = Template::new(FieldType::u32(Endianness::Big), "seconds".to_string());
+    seconds.timestamp = TimeScale::parse("s");
+
+    let mut millis = Template::new(FieldType::i16(Endianness::Big), "millis".to_string());
+    millis.timestamp = TimeScale::parse("ms@2000-01-01");
+
+    let mut float_seconds = Template::new(FieldType::double(Endianness::Big), "float seconds".to_string());
+    float_seconds.timestamp = TimeScale::parse("s");
+
+    let templates = vec!(seconds, millis, float_seconds);
+
+    let mut input: &[u8] = b"seconds,millis,float seconds\n1970-01-01T00:01:00Z,1999-12-31T23:59:59Z,1970-01-01T00:00:01.5\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x00, 0x00, 0x00, 0x3C,
+                            0xFC, 0x18,
+                            0x3F, 0xF8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00));
+
+    // times that do not fit in their field are an error
+    let mut input: &[u8] = b"seconds,millis,float seconds\n1969-12-31T23:59:59Z,2000-01-01,1970-01-01\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+
+    let mut input: &[u8] = b"seconds,millis,float seconds\n1970-01-01,2000-01-01T00:01:00Z,1970-01-01\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_count_field() {
+    let byte = FieldType::u8(Endianness::Big);
+
+    let mut samples = Template::new(FieldType::u16(Endianness::Big), "samples".to_string());
+    samples.count_field = Some(FieldRef::new("count"));
+
+    let mut entries = Template::structure("entry.csv".to_string(),
+                                          vec!(Template::new(byte, "id".to_string()), Template::new(byte, "value".to_string())),
+                                          "entries".to_string(),
+                                          None);
+    entries.count_field = Some(FieldRef::new("num entries"));
+
+    let templates = vec!(Template::new(byte, "count".to_string()),
+                         samples,
+                         Template::new(byte, "num entries".to_string()),
+                         entries);
+
+    let mut input: &[u8] = b"count,samples,num entries,entries.id,entries.value\n2,1;2,1,7,8\n0,,0,,\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x02, 0x00, 0x01, 0x00, 0x02, 0x01, 0x07, 0x08, 0x00, 0x00));
+
+    let mut input: &[u8] = b"type,description,value\nuint8_be,count,1\nuint16_be,samples[0],1\nuint8_be,num entries,2\n\
+                             uint8_be,entries[0].id,7\nuint8_be,entries[0].value,8\nuint8_be,entries[1].id,9\nuint8_be,entries[1].value,10\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x01, 0x00, 0x01, 0x02, 0x07, 0x08, 0x09, 0x0A));
+
+    // repeated columns must have as many values as their count
+    let mut input: &[u8] = b"count,samples,num entries,entries.id,entries.value\n2,1,0,,\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+
+    let mut input: &[u8] = b"count,samples,num entries,entries.id,entries.value\n1,1;2,0,,\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_to_value_blob_invalid() {
+    assert_eq!(to_value(FieldType::Blob(BlobFormat::Hex), "0G"), None);
+    assert_eq!(to_value(FieldType::Blob(BlobFormat::Base64), "!!!!"), None);
+}
+
+#[test]
+fn test_encode_blobs() {
+    let mut data = Template::new(FieldType::Blob(BlobFormat::Hex), "data".to_string());
+    data.length_field = Some(FieldRef::parse("packetLen+1"));
+
+    let mut name = Template::new(FieldType::Blob(BlobFormat::Text(TextEncoding::Utf8)), "name".to_string());
+    name.length_field = Some(FieldRef::new("nameLen"));
+
+    let templates = vec!(Template::new(FieldType::u16(Endianness::Big), "packetLen".to_string()),
+                         data,
+                         Template::new(FieldType::u8(Endianness::Big), "nameLen".to_string()),
+                         name);
+
+    // lengths are filled in from the blobs, whether they are given or left blank
+    let mut input: &[u8] = "packetLen,data,nameLen,name\n,01 02 03,,h\u{e9}\n7,ff,0,\n".as_bytes();
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x00, 0x02, 0x01, 0x02, 0x03, 0x03, 0x68, 0xC3, 0xA9,
+                            0x00, 0x00, 0xFF, 0x00));
+
+    let mut input: &[u8] = b"type,description,value\nuint16_be,packetLen,\nhex,data,0102\nuint8_be,nameLen,\ntext_utf8,name,hi\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x00, 0x01, 0x01, 0x02, 0x02, 0x68, 0x69));
+
+    // blobs can not be placed within a bit field
+    let mut input: &[u8] = b"type,description,value\nuint4_be,a,1\nhex,data,0102\nuint4_be,b,2\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &[], true, ValuesMode::Converted, &Framing::default()).is_none());
+
+    // a blob shorter than the adjustment has no length to write out
+    let mut input: &[u8] = b"packetLen,data,nameLen,name\n,,,\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_present_if() {
+    let mut time = Template::new(FieldType::u16(Endianness::Big), "time".to_string());
+    time.present_if = Some(Condition::parse("secHeaderFlag == 1").unwrap());
+
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "secHeaderFlag".to_string()),
+                         time,
+                         Template::new(FieldType::u8(Endianness::Big), "apid".to_string()));
+
+    // absent fields are left out, even when their column is given
+    let mut input: &[u8] = b"secHeaderFlag,time,apid\n1,258,3\n0,,4\n0,5,6\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x01, 0x01, 0x02, 0x03, 0x00, 0x04, 0x00, 0x06));
+
+    let mut input: &[u8] = b"type,description,value\nuint8_be,secHeaderFlag,0\nuint8_be,apid,4\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x00, 0x04));
+}
+
+#[test]
+fn test_encode_union() {
+    let byte = FieldType::u8(Endianness::Big);
+
+    let branch = |value, fields| Branch { value, structure: Structure { name: "branch.csv".to_string(), fields } };
+    let branches = vec!(branch(Some(1), vec!(Template::new(FieldType::u16(Endianness::Big), "temperature".to_string()))),
+                        branch(None, vec!(Template::new(byte, "mode".to_string()))));
+
+    let templates = vec!(Template::new(byte, "apid".to_string()),
+                         Template::union("bodies.csv".to_string(), FieldRef::new("apid"), branches, "body".to_string()),
+                         Template::new(byte, "crc".to_string()));
+
+    let mut input: &[u8] = b"apid,body.temperature,body.mode,crc\n1,16,,170\n5,,3,187\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x01, 0x00, 0x10, 0xAA, 0x05, 0x03, 0xBB));
+
+    let mut input: &[u8] = b"type,description,value\nuint8_be,apid,5\nuint8_be,body.mode,3\nuint8_be,crc,187\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x05, 0x03, 0xBB));
+
+    // the fields given must be those of the branch chosen by the record
+    let mut input: &[u8] = b"apid,body.temperature,body.mode,crc\n1,16,3,170\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+
+    let mut input: &[u8] = b"type,description,value\nuint8_be,apid,1\nuint8_be,body.mode,3\nuint8_be,crc,187\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_constant() {
+    let mut sync = Template::new(FieldType::u16(Endianness::Big), "sync".to_string());
+    sync.constant = Some("6900".to_string());
+
+    let mut magic = Template::new(FieldType::string(4), "magic".to_string());
+    magic.constant = Some("RIFF".to_string());
+
+    let templates = vec!(sync, magic, Template::new(FieldType::u8(Endianness::Big), "value".to_string()));
+
+    // constants have no column, and are written out for each record
+    let mut input: &[u8] = b"value\n5\n6\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, b"\x1A\xF4RIFF\x05\x1A\xF4RIFF\x06".to_vec());
+
+    // rows may leave out constants or give them, as long as they hold their constant
+    let mut input: &[u8] = b"type,description,value\nuint16_be,sync,6900\nuint8_be,value,5\nuint8_be,value,6\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, b"\x1A\xF4RIFF\x05\x1A\xF4RIFF\x06".to_vec());
+
+    let mut input: &[u8] = b"type,description,value\nuint16_be,sync,6901\nuint8_be,value,5\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).is_none());
+}
+
+#[test]
+fn test_encode_checksum() {
+    let mut crc = Template::new(FieldType::Checksum(ChecksumKind::Crc16Ccitt, Endianness::Big), "crc".to_string());
+    crc.span = Span::parse("1..");
+
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "version".to_string()),
+                         Template::new(FieldType::string(9), "data".to_string()),
+                         crc,
+                         Template::new(FieldType::Checksum(ChecksumKind::Crc32, Endianness::Little), "crc32".to_string()));
+
+    // checksums are computed whatever value is given, with their valid columns skipped
+    let mut input: &[u8] = b"version,data,crc,crc valid,crc32,crc32 valid\n1,123456789,,,5,false\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+
+    let crc32 = ChecksumKind::Crc32.compute(b"\x01123456789\x29\xB1") as u32;
+    let mut expected = b"\x01123456789\x29\xB1".to_vec();
+    expected.extend_from_slice(&crc32.to_le_bytes());
+    assert_eq!(output, expected);
+
+    // checksum rows and their valid rows may be given or left out
+    let mut input: &[u8] = b"type,description,value\nuint8_be,version,1\nstring9,data,123456789\n\
+                             crc16_ccitt_be,crc,0\nvalid,crc valid,false\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_encode_sync_marker() {
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "value".to_string()));
+    let framing = Framing { sync: Some(SyncMarker(vec!(0xEB, 0x90))), ..Default::default() };
+
+    let mut input: &[u8] = b"value\n1\n2\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing).unwrap();
+    assert_eq!(output, vec!(0xEB, 0x90, 0x01, 0xEB, 0x90, 0x02));
+}
+
+#[test]
+fn test_encode_record_length() {
+    let mut rest = Template::new(FieldType::Blob(BlobFormat::Hex), "extra".to_string());
+    rest.record_length = Some(FieldRef::parse("packetLen+2"));
+
+    let mut templates = vec!(Template::new(FieldType::u8(Endianness::Big), "packetLen".to_string()),
+                             Template::new(FieldType::u8(Endianness::Big), "first".to_string()),
+                             rest);
+
+    // records are filled with zeros up to their length
+    let mut input: &[u8] = b"packetLen,first,extra\n1,10,\n3,11,CC\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x01, 0x0A, 0x00, 0x03, 0x0B, 0xCC, 0x00, 0x00));
+
+    let mut input: &[u8] = b"packetLen,first,extra\n0,10,CC\n";
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).is_none());
+
+    // the rest of a record without a description has no column
+    templates[2].description = String::new();
+    let mut input: &[u8] = b"packetLen,first\n2,10\n";
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &Framing::default()).unwrap();
+    assert_eq!(output, vec!(0x02, 0x0A, 0x00, 0x00));
+}
+
+#[test]
+fn test_encode_framing() {
+    let templates = vec!(Template::new(FieldType::u8(Endianness::Big), "value".to_string()));
+
+    let mut input: &[u8] = b"value\n1\n2\n3\n";
+    let framing = Framing { skip: 2, stride: Some(3), count: Some(2), ..Default::default() };
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing).unwrap();
+    assert_eq!(output, vec!(0x00, 0x00, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00));
+
+    let mut input: &[u8] = b"type,description,value\nuint8_be,value,1\nuint8_be,value,2\n";
+    let framing = Framing { sync: Some(SyncMarker(vec!(0xEB, 0x90))), stride: Some(4), count: Some(1), ..Default::default() };
+    let mut output: Vec<u8> = Vec::new();
+    encode(&mut input, &mut output, &templates, true, ValuesMode::Converted, &framing).unwrap();
+    assert_eq!(output, vec!(0xEB, 0x90, 0x01, 0x00));
+
+    let mut input: &[u8] = b"value\n1\n";
+    let framing = Framing { sync: Some(SyncMarker(vec!(0xEB, 0x90))), stride: Some(2), ..Default::default() };
+    let mut output: Vec<u8> = Vec::new();
+    assert!(encode(&mut input, &mut output, &templates, false, ValuesMode::Converted, &framing).is_none());
+}
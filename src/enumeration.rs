@@ -0,0 +1,174 @@
+use std::fs::File;
+use std::path::Path;
+
+use crate::template::parse_integer;
+
+
+/// The label used for values that are not listed in an enumeration.
+#[derive(Eq, PartialEq, Debug, Clone, Deserialize)]
+pub enum Fallback {
+    /// Output the given label for unknown values
+    Label(String),
+
+    /// Output unknown values as their raw number
+    Raw,
+}
+
+/// An enumeration maps the raw values of an integer field to labels.
+///
+/// An enumeration is written as a list of 'value=label' entries separated by
+/// semicolons, such as '0=IDLE;1=SAFE;2=SCIENCE'. An entry of '*=LABEL' gives
+/// a label for values not in the list, and '*=' outputs them as numbers. Without
+/// a '*' entry, values not in the list are an error.
+#[derive(Eq, PartialEq, Debug, Clone, Deserialize)]
+pub struct Enumeration {
+    pub entries: Vec<(i128, String)>,
+    pub fallback: Option<Fallback>,
+}
+
+impl Enumeration {
+    /// Parse an enumeration given as 'value=label' entries separated by semicolons.
+    pub fn parse(text: &str) -> Option<Enumeration> {
+        let mut enumeration = Enumeration { entries: vec!(), fallback: None };
+
+        for entry in text.split(';').map(|entry| entry.trim()).filter(|entry| !entry.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let value = parts.next()?.trim();
+
+            let label = match parts.next() {
+                Some(label) => label.trim(),
+
+                None => {
+                    error!("Enumeration entry '{}' should be given as 'value=label'", entry);
+                    return None;
+                },
+            };
+
+            enumeration.add_entry(value, label)?;
+        }
+
+        Some(enumeration)
+    }
+
+    /// Read an enumeration from a csv file with a 'value' and 'label' column.
+    pub fn read_file(file_name: &Path) -> Option<Enumeration> {
+        let file = File::open(file_name).map_err(|_| error!("Could not open enumeration file '{}'!", file_name.display())).ok()?;
+
+        let mut lines = csv::Reader::from_reader(file);
+        info!("Opened Enumeration File {}", file_name.display());
+
+        let mut enumeration = Enumeration { entries: vec!(), fallback: None };
+
+        for record in lines.records() {
+            let rec = record.ok()?;
+
+            if rec.len() < 2 {
+                error!("Enumeration file '{}' should have a value and label on each line", file_name.display());
+                return None;
+            }
+
+            enumeration.add_entry(rec[0].trim(), rec[1].trim())?;
+        }
+
+        Some(enumeration)
+    }
+
+    fn add_entry(&mut self, value: &str, label: &str) -> Option<()> {
+        if value == "*" {
+            if label.is_empty() {
+                self.fallback = Some(Fallback::Raw);
+            } else {
+                self.fallback = Some(Fallback::Label(label.to_string()));
+            }
+        } else {
+            let value = match parse_signed(value) {
+                Some(value) => value,
+
+                None => {
+                    error!("Enumeration value '{}' is not an integer", value);
+                    return None;
+                },
+            };
+
+            if label.is_empty() {
+                error!("Enumeration value {} has no label", value);
+                return None;
+            }
+
+            self.entries.push((value, label.to_string()));
+        }
+
+        Some(())
+    }
+
+    /// The label for a raw value, or None if the value is unknown and there is no fallback.
+    pub fn label(&self, value: i128) -> Option<String> {
+        match self.entries.iter().find(|(entry_value, _)| *entry_value == value) {
+            Some((_, label)) => Some(label.clone()),
+
+            None => {
+                match &self.fallback {
+                    Some(Fallback::Label(label)) => Some(label.clone()),
+                    Some(Fallback::Raw) => Some(value.to_string()),
+                    None => None,
+                }
+            },
+        }
+    }
+
+    /// The raw value for a label or a number, given as text to encode.
+    pub fn value(&self, text: &str) -> Option<String> {
+        let text = text.trim();
+
+        match self.entries.iter().find(|(_, label)| label == text) {
+            Some((value, _)) => Some(value.to_string()),
+
+            None => parse_signed(text).map(|value| value.to_string()),
+        }
+    }
+}
+
+/// Parse an integer which may be negative, given either in decimal
+/// or in hex with a '0x' prefix.
+pub fn parse_signed(text: &str) -> Option<i128> {
+    let text = text.trim();
+
+    match text.strip_prefix('-') {
+        Some(magnitude) => parse_integer(magnitude).filter(|value| *value <= i128::MAX as u128).map(|value| -(value as i128)),
+        None => parse_integer(text).filter(|value| *value <= i128::MAX as u128).map(|value| value as i128),
+    }
+}
+
+#[test]
+fn test_enumeration_parse() {
+    let enumeration = Enumeration::parse("0=IDLE; 1=SAFE;0x2=SCIENCE;-1=ERROR").unwrap();
+
+    assert_eq!(enumeration.entries,
+               vec!((0, "IDLE".to_string()), (1, "SAFE".to_string()),
+                    (2, "SCIENCE".to_string()), (-1, "ERROR".to_string())));
+    assert_eq!(enumeration.fallback, None);
+
+    assert_eq!(Enumeration::parse("0=IDLE;*=UNKNOWN").unwrap().fallback, Some(Fallback::Label("UNKNOWN".to_string())));
+    assert_eq!(Enumeration::parse("0=IDLE;*=").unwrap().fallback, Some(Fallback::Raw));
+
+    assert!(Enumeration::parse("0").is_none());
+    assert!(Enumeration::parse("zero=IDLE").is_none());
+    assert!(Enumeration::parse("0=").is_none());
+}
+
+#[test]
+fn test_enumeration_lookup() {
+    let enumeration = Enumeration::parse("0=IDLE;1=SAFE").unwrap();
+
+    assert_eq!(enumeration.label(1), Some("SAFE".to_string()));
+    assert_eq!(enumeration.label(5), None);
+    assert_eq!(enumeration.value("SAFE"), Some("1".to_string()));
+    assert_eq!(enumeration.value("5"), Some("5".to_string()));
+    assert_eq!(enumeration.value("BOOT"), None);
+
+    let enumeration = Enumeration::parse("0=IDLE;*=UNKNOWN").unwrap();
+    assert_eq!(enumeration.label(5), Some("UNKNOWN".to_string()));
+
+    let enumeration = Enumeration::parse("0=IDLE;*=").unwrap();
+    assert_eq!(enumeration.label(5), Some("5".to_string()));
+}
@@ -0,0 +1,174 @@
+//! Conversions for the small floating point formats, half precision (IEEE 754 binary16)
+//! and bfloat16, which have no native Rust types. Values of these types are held in an
+//! f32, which can represent every value of both formats exactly.
+
+
+/// Number of exponent and mantissa bits in a half precision float.
+const HALF_FORMAT: (u32, u32) = (5, 10);
+
+/// Number of exponent and mantissa bits in a bfloat16.
+const BFLOAT16_FORMAT: (u32, u32) = (8, 7);
+
+pub fn half_from_bits(bits: u16) -> f32 {
+    from_bits(bits as u64, HALF_FORMAT) as f32
+}
+
+/// Round a value to the nearest half precision float, with ties to even.
+pub fn half_to_bits(value: f64) -> u16 {
+    to_bits(value, HALF_FORMAT) as u16
+}
+
+pub fn bfloat16_from_bits(bits: u16) -> f32 {
+    from_bits(bits as u64, BFLOAT16_FORMAT) as f32
+}
+
+/// Round a value to the nearest bfloat16, with ties to even.
+pub fn bfloat16_to_bits(value: f64) -> u16 {
+    to_bits(value, BFLOAT16_FORMAT) as u16
+}
+
+/// Decode the bits of a float with the given number of exponent and mantissa bits.
+fn from_bits(bits: u64, (exponent_bits, mantissa_bits): (u32, u32)) -> f64 {
+    let bias = (1i32 << (exponent_bits - 1)) - 1;
+    let max_exponent = (1u64 << exponent_bits) - 1;
+
+    let sign = if (bits >> (exponent_bits + mantissa_bits)) & 1 == 1 { -1.0 } else { 1.0 };
+    let exponent = (bits >> mantissa_bits) & max_exponent;
+    let mantissa = bits & ((1 << mantissa_bits) - 1);
+
+    if exponent == max_exponent {
+        if mantissa == 0 {
+            sign * f64::INFINITY
+        } else {
+            f64::NAN
+        }
+    } else if exponent == 0 {
+        // subnormal numbers have no implicit leading bit
+        sign * mantissa as f64 * 2f64.powi(1 - bias - mantissa_bits as i32)
+    } else {
+        let significand = (1 << mantissa_bits) | mantissa;
+        sign * significand as f64 * 2f64.powi(exponent as i32 - bias - mantissa_bits as i32)
+    }
+}
+
+/// Round a value to the bits of a float with the given number of exponent and
+/// mantissa bits, rounding to nearest with ties to even. Values too large for the
+/// format become infinity, and NaNs become a quiet NaN.
+fn to_bits(value: f64, (exponent_bits, mantissa_bits): (u32, u32)) -> u64 {
+    let bits = value.to_bits();
+
+    let bias = (1i64 << (exponent_bits - 1)) - 1;
+    let max_exponent = (1u64 << exponent_bits) - 1;
+    let infinity = max_exponent << mantissa_bits;
+
+    let sign = (bits >> 63) << (exponent_bits + mantissa_bits);
+    let exponent = ((bits >> 52) & 0x7FF) as i64;
+    let mantissa = bits & ((1 << 52) - 1);
+
+    if exponent == 0x7FF {
+        if mantissa == 0 {
+            return sign | infinity;
+        } else {
+            return sign | infinity | (1 << (mantissa_bits - 1));
+        }
+    }
+
+    // f64 subnormals have no implicit leading bit
+    let (significand, unbiased_exponent) = if exponent == 0 {
+        (mantissa, -1022)
+    } else {
+        ((1 << 52) | mantissa, exponent - 1023)
+    };
+
+    // numbers below the smallest normal number are shifted down into a subnormal
+    let target_exponent = unbiased_exponent + bias;
+    let mut shift = (52 - mantissa_bits) as i64;
+    if target_exponent < 1 {
+        shift += 1 - target_exponent;
+    }
+
+    // anything shifted this far is less than half of the smallest subnormal
+    if shift > 60 {
+        return sign;
+    }
+
+    let mut rounded = significand >> shift;
+    let remainder = significand & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if remainder > halfway || (remainder == halfway && (rounded & 1) == 1) {
+        rounded += 1;
+    }
+
+    // normal numbers include their leading bit in 'rounded', so the exponent is
+    // added one lower. This also carries a rounded up mantissa into the exponent.
+    let result = if target_exponent >= 1 {
+        ((target_exponent as u64 - 1) << mantissa_bits) + rounded
+    } else {
+        rounded
+    };
+
+    if result >= infinity {
+        sign | infinity
+    } else {
+        sign | result
+    }
+}
+
+#[test]
+fn test_half_from_bits() {
+    assert_eq!(half_from_bits(0x3C00), 1.0);
+    assert_eq!(half_from_bits(0xC000), -2.0);
+    assert_eq!(half_from_bits(0x7BFF), 65504.0);
+    assert_eq!(half_from_bits(0x0001), 2f32.powi(-24));
+    assert_eq!(half_from_bits(0x7C00), f32::INFINITY);
+    assert_eq!(half_from_bits(0xFC00), f32::NEG_INFINITY);
+    assert!(half_from_bits(0x7E00).is_nan());
+}
+
+#[test]
+fn test_half_to_bits() {
+    assert_eq!(half_to_bits(1.0), 0x3C00);
+    assert_eq!(half_to_bits(-2.0), 0xC000);
+    assert_eq!(half_to_bits(0.1), 0x2E66);
+    assert_eq!(half_to_bits(65504.0), 0x7BFF);
+    assert_eq!(half_to_bits(-0.0), 0x8000);
+
+    // ties round to even
+    assert_eq!(half_to_bits(1.0 + 2f64.powi(-11)), 0x3C00);
+    assert_eq!(half_to_bits(1.0 + 3.0 * 2f64.powi(-11)), 0x3C02);
+
+    // rounding up can carry into the exponent, or overflow to infinity
+    assert_eq!(half_to_bits(2.0 - 2f64.powi(-12)), 0x4000);
+    assert_eq!(half_to_bits(65520.0), 0x7C00);
+    assert_eq!(half_to_bits(65519.0), 0x7BFF);
+    assert_eq!(half_to_bits(1e10), 0x7C00);
+
+    // subnormals, and values too small to represent
+    assert_eq!(half_to_bits(2f64.powi(-24)), 0x0001);
+    assert_eq!(half_to_bits(2f64.powi(-25)), 0x0000);
+    assert_eq!(half_to_bits(1.5 * 2f64.powi(-25)), 0x0001);
+    assert_eq!(half_to_bits(2f64.powi(-14) - 2f64.powi(-25)), 0x0400);
+    assert_eq!(half_to_bits(1e-300), 0x0000);
+
+    assert_eq!(half_to_bits(f64::INFINITY), 0x7C00);
+    assert_eq!(half_to_bits(f64::NEG_INFINITY), 0xFC00);
+    assert!(half_from_bits(half_to_bits(f64::NAN)).is_nan());
+}
+
+#[test]
+fn test_bfloat16_bits() {
+    assert_eq!(bfloat16_to_bits(1.0), 0x3F80);
+    assert_eq!(bfloat16_to_bits(-2.0), 0xC000);
+    assert_eq!(bfloat16_to_bits(1.3), 0x3FA6);
+    assert_eq!(bfloat16_to_bits(1e39), 0x7F80);
+    assert_eq!(bfloat16_to_bits(f64::NEG_INFINITY), 0xFF80);
+    assert!(bfloat16_from_bits(bfloat16_to_bits(f64::NAN)).is_nan());
+
+    assert_eq!(bfloat16_from_bits(0x3F80), 1.0);
+    assert_eq!(bfloat16_from_bits(0x4049), 3.140625);
+
+    // every bfloat16 round trips through an f32
+    for bits in 0..0x7F80u16 {
+        assert_eq!(bfloat16_to_bits(bfloat16_from_bits(bits) as f64), bits);
+    }
+}
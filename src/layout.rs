@@ -0,0 +1,394 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::types::*;
+use crate::template::*;
+
+
+/// A single field of a record, as laid out from the templates.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Element<'a> {
+    pub template: &'a Template,
+
+    /// The description of this field with every array index, such as 'entries[2].id'
+    pub description: String,
+
+    /// The column of this field in column based files, which leaves out the index
+    /// of fields repeated by a count field, such as 'entries.id'
+    pub column: String,
+
+    /// Whether this field is repeated by a count field, so its column holds a
+    /// value for each repetition
+    pub repeated: bool,
+
+    /// Whether this field is within a branch of a union, so it is only present
+    /// in records choosing that branch
+    pub in_branch: bool,
+
+    /// The description of the field giving the length of a blob, or of the whole
+    /// record, and the length it gives if its value is a valid length, once the
+    /// record's values are known
+    pub length_field: Option<String>,
+    pub length: Option<usize>,
+}
+
+/// The raw values of the fields laid out so far in a record, by description.
+type RecordValues = HashMap<String, Value>;
+
+/// Lay out a single record, visiting each of its fields in turn. The visitor
+/// returns the raw value of each field, which gives the count or length of any
+/// later fields referring to it, or None to stop.
+pub fn walk_record<'a, F>(templates: &'a [Template], visit: &mut F) -> Option<()>
+    where F: FnMut(&Element<'a>) -> Option<Value> {
+
+    let mut values = RecordValues::new();
+    let mut scopes = vec!(String::new());
+
+    walk_templates(templates, "", "", false, false, &mut scopes, &mut Some(&mut values), visit)
+}
+
+/// The columns of a record in column based files. Fields repeated by a count
+/// field have a single column, holding a value for each repetition, and fields
+/// with the same description in different branches of a union share a column.
+pub fn record_columns(templates: &[Template]) -> Vec<Element<'_>> {
+    let mut columns: Vec<Element> = vec!();
+    let mut scopes = vec!(String::new());
+
+    walk_templates(templates, "", "", false, false, &mut scopes, &mut None, &mut |element| {
+        if !columns.iter().any(|column| column.column == element.column) {
+            columns.push(element.clone());
+        }
+        Some(Value::Bool(true))
+    });
+
+    columns
+}
+
+/// Visit the fields of the given templates. Without record values, fields
+/// repeated by a count field are visited once, without an index, and every
+/// branch of a union is visited.
+#[allow(clippy::too_many_arguments)]
+fn walk_templates<'a, F>(templates: &'a [Template],
+                         prefix: &str,
+                         column_prefix: &str,
+                         repeated: bool,
+                         in_branch: bool,
+                         scopes: &mut Vec<String>,
+                         values: &mut Option<&mut RecordValues>,
+                         visit: &mut F) -> Option<()>
+    where F: FnMut(&Element<'a>) -> Option<Value> {
+
+    for template in templates {
+        // a field whose condition does not hold is absent from the record, while
+        // without record values every field that may be present is visited
+        if let (Some(condition), Some(values)) = (&template.present_if, values.as_deref()) {
+            let present = condition.evaluate(&|field: &str| {
+                scopes.iter().rev().find_map(|scope| values.get(&format!("{}{}", scope, field)))
+            });
+
+            match present {
+                Some(true) => {},
+
+                Some(false) => continue,
+
+                None => {
+                    error!("Could not evaluate condition '{}' of field '{}'", condition, template.description);
+                    return None;
+                },
+            }
+        }
+
+        // the number of repetitions of a field, whether they are indexed, and
+        // whether the index is part of the column name
+        let (count, indexed, in_column) = match (template.count, &template.count_field, values.as_deref()) {
+            (Some(count), _, _) => (count, true, true),
+
+            (None, Some(count_field), Some(values)) => {
+                let (_, value) = lookup_field(values, scopes, count_field, &template.description, "Count")?;
+
+                match size_of(value, count_field) {
+                    Some(count) => (count, true, false),
+
+                    None => {
+                        error!("Count field '{}' of field '{}' has value {}, which is not a count",
+                               count_field, template.description, value.to_string());
+                        return None;
+                    },
+                }
+            },
+
+            (None, Some(_), None) => (1, false, false),
+
+            (None, None, _) => (1, false, false),
+        };
+
+        let repeated = repeated || template.count_field.is_some();
+
+        for index in 0..count {
+            let description = if indexed {
+                format!("{}{}[{}]", prefix, template.description, index)
+            } else {
+                format!("{}{}", prefix, template.description)
+            };
+
+            let column = if in_column {
+                format!("{}{}[{}]", column_prefix, template.description, index)
+            } else {
+                format!("{}{}", column_prefix, template.description)
+            };
+
+            match (&template.structure, &template.union) {
+                // the fields of a structure may refer to count fields within the
+                // structure, or in any structure containing it
+                (Some(structure), _) => {
+                    let prefix = format!("{}.", description);
+                    scopes.push(prefix.clone());
+
+                    let result = walk_templates(&structure.fields,
+                                                &prefix,
+                                                &format!("{}.", column),
+                                                repeated,
+                                                in_branch,
+                                                scopes,
+                                                values,
+                                                visit);
+                    scopes.pop();
+                    result?;
+                },
+
+                // the fields of a union's branch are laid out like a structure
+                (None, Some(union)) => {
+                    let branches: Vec<&Branch> = match values.as_deref() {
+                        Some(values) => {
+                            let (_, value) = lookup_field(values, scopes, &union.discriminator, &description, "Discriminator")?;
+
+                            match value.as_integer().and_then(|value| union.branch(value)) {
+                                Some(branch) => vec!(branch),
+
+                                None => {
+                                    error!("Discriminator '{}' of union '{}' has value {}, which has no branch",
+                                           union.discriminator, description, value.to_string());
+                                    return None;
+                                },
+                            }
+                        },
+
+                        None => union.branches.iter().collect(),
+                    };
+
+                    let prefix = format!("{}.", description);
+                    scopes.push(prefix.clone());
+
+                    for branch in branches {
+                        let result = walk_templates(&branch.structure.fields,
+                                                    &prefix,
+                                                    &format!("{}.", column),
+                                                    repeated,
+                                                    true,
+                                                    scopes,
+                                                    values,
+                                                    visit);
+
+                        if result.is_none() {
+                            scopes.pop();
+                            return None;
+                        }
+                    }
+
+                    scopes.pop();
+                },
+
+                (None, None) => {
+                    // a blob's length, or the record's, is found in the same way as a count,
+                    // but is only checked when it is used, as encoding fills it in
+                    let field_ref = template.length_field.as_ref().or(template.record_length.as_ref());
+
+                    let (length_field, length) = match (field_ref, values.as_deref()) {
+                        (Some(field_ref), Some(values)) => {
+                            let (length_field, value) = lookup_field(values, scopes, field_ref, &description, "Length")?;
+                            (Some(length_field), size_of(value, field_ref))
+                        },
+
+                        _ => (None, None),
+                    };
+
+                    let element = Element { template, description, column, repeated, in_branch, length_field, length };
+
+                    let value = visit(&element)?;
+
+                    if let Some(values) = values {
+                        values.insert(element.description, value);
+                    }
+                },
+            }
+        }
+    }
+
+    Some(())
+}
+
+/// Find the field a count or length refers to, looking in the innermost
+/// structure first, and returning its description along with its value.
+fn lookup_field<'v>(values: &'v RecordValues,
+                    scopes: &[String],
+                    field_ref: &FieldRef,
+                    description: &str,
+                    kind: &str) -> Option<(String, &'v Value)> {
+    let found = scopes.iter()
+                      .rev()
+                      .map(|scope| format!("{}{}", scope, field_ref.field))
+                      .find_map(|name| values.get(&name).map(|value| (name, value)));
+
+    if found.is_none() {
+        error!("{} field '{}' of field '{}' has not been read", kind, field_ref.field, description);
+    }
+
+    found
+}
+
+/// The size given by the value of a count or length field, once adjusted.
+fn size_of(value: &Value, field_ref: &FieldRef) -> Option<usize> {
+    value.as_integer()
+         .and_then(|size| size.checked_add(field_ref.adjustment))
+         .and_then(|size| usize::try_from(size).ok())
+}
+
+/// Escape the value of a repeated field, so it can be joined with the values of the
+/// field's other repetitions into one column, separated by semicolons.
+pub fn escape_repeated(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(';', "\\;")
+}
+
+/// Split the column of a repeated field into the value of each repetition, at each
+/// semicolon that is not escaped with a backslash.
+pub fn split_repeated(cell: &str) -> Vec<String> {
+    let mut values = vec!(String::new());
+    let mut chars = cell.chars();
+
+    while let Some(chr) = chars.next() {
+        let value = values.last_mut().unwrap();
+
+        match chr {
+            '\\' => value.extend(chars.next()),
+            ';' => values.push(String::new()),
+            _ => value.push(chr),
+        }
+    }
+
+    values
+}
+
+#[test]
+fn test_record_columns() {
+    let typ = FieldType::u16(Endianness::Little);
+    let templates = vec!(Template::array(typ, "samples".to_string(), 3));
+
+    let columns = record_columns(&templates);
+    let descriptions: Vec<&str> = columns.iter().map(|element| element.description.as_str()).collect();
+
+    assert_eq!(templates[0].num_bits(), 48);
+    assert_eq!(templates[0].type_name(), "uint16_le[3]");
+    assert_eq!(descriptions, vec!("samples[0]", "samples[1]", "samples[2]"));
+    assert!(columns.iter().all(|element| element.column == element.description && !element.repeated));
+}
+
+#[test]
+fn test_repeated_values() {
+    assert_eq!(escape_repeated("a;b\\c"), "a\\;b\\\\c");
+    assert_eq!(split_repeated("1;2"), vec!("1", "2"));
+    assert_eq!(split_repeated("a\\;b;\\\\;c"), vec!("a;b", "\\", "c"));
+
+    let values = vec!("x;y", "z\\", "");
+    let cell = values.iter().map(|value| escape_repeated(value)).collect::<Vec<String>>().join(";");
+    assert_eq!(split_repeated(&cell), values);
+}
+
+#[test]
+fn test_record_columns_structure() {
+    let fields = vec!(Template::new(FieldType::u8(Endianness::Big), "version".to_string()),
+                      Template::array(FieldType::u16(Endianness::Big), "apid".to_string(), 2));
+    let templates = vec!(Template::structure("header.csv".to_string(), fields, "header".to_string(), Some(2)));
+
+    assert_eq!(templates[0].num_bits(), 80);
+    assert_eq!(templates[0].type_name(), "struct:header.csv[2]");
+
+    let descriptions: Vec<String> = record_columns(&templates).into_iter().map(|element| element.description).collect();
+    assert_eq!(descriptions,
+               vec!("header[0].version", "header[0].apid[0]", "header[0].apid[1]",
+                    "header[1].version", "header[1].apid[0]", "header[1].apid[1]"));
+}
+
+#[test]
+fn test_walk_record_count_field() {
+    let byte = FieldType::u8(Endianness::Big);
+
+    let mut samples = Template::new(byte, "samples".to_string());
+    samples.count_field = Some(FieldRef::new("count"));
+
+    let mut entries = Template::structure("entry.csv".to_string(),
+                                          vec!(Template::new(byte, "count".to_string()), samples.clone()),
+                                          "entries".to_string(),
+                                          None);
+    entries.count_field = Some(FieldRef::new("count"));
+
+    let templates = vec!(Template::new(byte, "count".to_string()), entries, samples);
+
+    let columns: Vec<(String, bool)> =
+        record_columns(&templates).into_iter().map(|element| (element.column, element.repeated)).collect();
+    assert_eq!(columns, vec!(("count".to_string(), false),
+                             ("entries.count".to_string(), true),
+                             ("entries.samples".to_string(), true),
+                             ("samples".to_string(), true)));
+
+    // the count of the inner samples comes from the count within each entry
+    let mut input = vec!(2, 1, 0, 0, 5, 6).into_iter();
+    let mut descriptions = vec!();
+    walk_record(&templates, &mut |element| {
+        descriptions.push(element.description.clone());
+        input.next().map(Value::Uint8)
+    }).unwrap();
+
+    assert_eq!(descriptions, vec!("count", "entries[0].count", "entries[0].samples[0]", "entries[1].count",
+                                  "samples[0]", "samples[1]"));
+
+    // counts must be read before the fields they repeat
+    let mut early = Template::new(byte, "early".to_string());
+    early.count_field = Some(FieldRef::new("count"));
+    assert!(walk_record(&[early], &mut |_| Some(Value::Uint8(1))).is_none());
+}
+
+#[test]
+fn test_walk_record_union() {
+    let byte = FieldType::u8(Endianness::Big);
+
+    let branch = |value, fields| Branch { value, structure: Structure { name: "branch.csv".to_string(), fields } };
+    let branches = vec!(branch(Some(1), vec!(Template::new(byte, "mode".to_string()), Template::new(byte, "flags".to_string()))),
+                        branch(Some(2), vec!(Template::new(byte, "mode".to_string()), Template::new(byte, "count".to_string()))),
+                        branch(None, vec!()));
+
+    let templates = vec!(Template::new(byte, "type".to_string()),
+                         Template::union("bodies.csv".to_string(), FieldRef::new("type"), branches, "body".to_string()));
+
+    assert!(templates[1].is_variable());
+    assert_eq!(templates[1].type_name(), "union:bodies.csv[type]");
+
+    // fields with the same description in different branches share a column
+    let columns: Vec<(String, bool)> =
+        record_columns(&templates).into_iter().map(|element| (element.column, element.in_branch)).collect();
+    assert_eq!(columns, vec!(("type".to_string(), false),
+                             ("body.mode".to_string(), true),
+                             ("body.flags".to_string(), true),
+                             ("body.count".to_string(), true)));
+
+    let descriptions = |input: Vec<u8>| {
+        let mut input = input.into_iter();
+        let mut descriptions = vec!();
+        walk_record(&templates, &mut |element| {
+            descriptions.push(element.description.clone());
+            input.next().map(Value::Uint8)
+        }).map(|_| descriptions)
+    };
+
+    assert_eq!(descriptions(vec!(2, 0, 0)), Some(vec!("type".to_string(), "body.mode".to_string(), "body.count".to_string())));
+    assert_eq!(descriptions(vec!(9)), Some(vec!("type".to_string())));
+}
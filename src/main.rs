@@ -9,24 +9,47 @@ extern crate loggerv;
 extern crate glob;
 
 mod types;
+mod options;
 mod bit_buffer;
+mod float;
+mod enumeration;
+mod calibration;
+mod varint;
+mod bcd;
+mod time;
+mod blob;
+mod checksum;
+mod reader;
 mod encode;
 mod decode;
 mod template;
+mod layout;
+mod condition;
 
 use std::fs::File;
-use std::io::{Write, Read, Cursor, BufReader, BufWriter};
+use std::io::{self, Write, Read, Cursor, BufReader, BufWriter};
+use std::process;
 
 use structopt::StructOpt;
 
 use log::{Level};
 
+use loggerv::Output;
+
 use glob::glob;
 
 use encode::*;
 use decode::*;
 use template::*;
+use types::{HasNumBits, ValuesMode};
+use options::{Framing, PartialRecord};
+
 
+/// The exit status when an input ends within a record.
+const PARTIAL_RECORD_STATUS: i32 = 2;
+
+/// The file name standing for stdin as an input, and stdout as an output.
+const STDIO: &str = "-";
 
 #[derive(Debug, StructOpt)]
 #[structopt(name="inlay", about="A command line tool for quickly reading and writing simple binary formats")]
@@ -45,9 +68,18 @@ enum Opt {
 
         #[structopt(short="r", long="rows", help="Encode row based file")]
         rows: bool,
+
+        #[structopt(long="values", default_value="converted", help="Values given for converted fields: raw, converted, or both")]
+        values: ValuesMode,
+
+        #[structopt(flatten)]
+        framing: Framing,
+
+        #[structopt(long="header", help="File holding the file header written before the first record, padded with zeros up to --skip")]
+        header_file: Option<String>,
      },
 
-     #[structopt(name="decode")]
+     #[structopt(name="decode", about="Decode binary files into csv files, exiting with status 2 if an input ends within a record")]
      Decode {
         template_file: String,
 
@@ -61,6 +93,15 @@ enum Opt {
 
         #[structopt(short="r", long="rows", help="Decode row based file")]
         rows: bool,
+
+        #[structopt(long="values", default_value="converted", help="Values written for converted fields: raw, converted, or both")]
+        values: ValuesMode,
+
+        #[structopt(flatten)]
+        framing: Framing,
+
+        #[structopt(flatten)]
+        partial: PartialRecord,
      },
 
      #[structopt(name="summary")]
@@ -72,6 +113,11 @@ enum Opt {
 fn expand_globs(input_files: Vec<String>) -> Vec<String> {
     let mut file_names = vec!();
     for file_name in input_files {
+        if file_name == STDIO {
+            file_names.push(file_name);
+            continue;
+        }
+
         for entry in glob(&file_name).expect(&format!("Could not glob input file name '{}'", file_name)) {
             file_names.push(entry.unwrap().to_str().unwrap().to_string().clone());
         }
@@ -80,14 +126,82 @@ fn expand_globs(input_files: Vec<String>) -> Vec<String> {
     file_names
 }
 
+/// Log to stderr when the output is written to stdout, so the log does not
+/// mix with the output, and otherwise log as usual.
+fn init_logging(log_level: Level, to_stdout: bool) {
+    let mut logger = loggerv::Logger::new().max_level(log_level);
+
+    if to_stdout {
+        for level in &[Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace] {
+            logger = logger.output(level, Output::Stderr);
+        }
+    }
+
+    logger.init().unwrap();
+}
+
+/// Whether output is written to stdout, either because it was asked for, or because
+/// there is no output file and the input is stdin, so no output file can be named after it.
+fn writes_stdout(out_file: &str, in_file_globs: &[String]) -> bool {
+    out_file == STDIO || (out_file.is_empty() && in_file_globs.iter().any(|in_file| in_file == STDIO))
+}
+
+/// The output file for an input file when no output file is given,
+/// which is stdout for stdin.
+fn output_file_name(in_file: &str, extension: &str) -> String {
+    if in_file == STDIO {
+        STDIO.to_string()
+    } else {
+        format!("{}{}", in_file, extension)
+    }
+}
+
+/// Open an input file, or stdin for '-'.
+fn open_input(in_file: &str) -> Box<dyn Read> {
+    if in_file == STDIO {
+        return Box::new(io::stdin());
+    }
+
+    let input_file = File::open(in_file).unwrap_or_else(|_| panic!("Could not open input file '{}'!", in_file));
+    Box::new(BufReader::new(input_file))
+}
+
+/// Read the contents of a header file to write before the records of an encoded file.
+fn read_header(header_file: &str) -> Vec<u8> {
+    let mut header = Vec::new();
+    open_input(header_file).read_to_end(&mut header)
+        .unwrap_or_else(|_| panic!("Could not read header file '{}'!", header_file));
+    header
+}
+
+/// Create an output file, or write to stdout for '-'. Encoding and decoding
+/// flush the output after each record, so a stream is written out as it is produced.
+fn create_output(out_file: &str) -> Box<dyn Write> {
+    if out_file == STDIO {
+        return Box::new(io::stdout());
+    }
+
+    let output_file = File::create(out_file).unwrap_or_else(|_| panic!("Could not open output file '{}'!", out_file));
+    Box::new(BufWriter::new(output_file))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn command_encode(template_file: String,
                   in_file_globs: Vec<String>,
                   out_file: String,
                   log_level: Level,
-                  rows: bool) {
-    loggerv::init_with_level(log_level).unwrap();
+                  rows: bool,
+                  values: ValuesMode,
+                  framing: Framing,
+                  header_file: Option<String>) {
+    init_logging(log_level, writes_stdout(&out_file, &in_file_globs));
      trace!("Encoding");
 
+    // a header file gives the start of the file header, and encoding fills
+    // the rest of the '--skip' bytes with zeros
+    let header = header_file.map(|header_file| read_header(&header_file)).unwrap_or_default();
+    let framing = Framing { skip: framing.skip.saturating_sub(header.len() as u64), ..framing };
+
     trace!("Expanding globs");
     let mut in_files = expand_globs(in_file_globs);
 
@@ -118,18 +232,16 @@ fn command_encode(template_file: String,
         error!("Outfile not supported when run with multiple input files!");
     } else if out_file.len() > 0 { // otherwise, if an output file was given
         trace!("Single output file {}", out_file);
-        let mut output = File::create(&out_file).expect(&format!("Cannot open output file {}!", out_file));
+        let mut output = create_output(&out_file);
         trace!("Output file open");
 
         trace!("{} input files to process", in_files.len());
         for in_file in in_files {
             trace!("Processing input file {}", in_file);
 
-            let mut input = File::open(&in_file)
-                                  .or_else(|err| { error!("Could not open input file '{}'!", &in_file);
-                                                            Err(err)
-                                                           }).ok().unwrap();
-            if let None = encode(&mut input, &mut output, &templates, rows) {
+            let mut input = open_input(&in_file);
+            output.write_all(&header).unwrap_or_else(|_| panic!("Could not write file header!"));
+            if let None = encode(&mut input, &mut output, &templates, rows, values, &framing) {
                 panic!("Encoding error!");
             } else {
                 trace!("File processed");
@@ -140,20 +252,17 @@ fn command_encode(template_file: String,
 
         trace!("{} input files to process", in_files.len());
         for in_file in in_files {
-            let mut out_file = in_file.clone();
-            out_file.push_str(".bin");
+            let out_file = output_file_name(&in_file, ".bin");
             info!("Outputting to {}", out_file);
 
             info!("Processing input file {}", in_file);
 
-            let mut output =
-                File::create(&out_file).expect(&format!("Cannot open output file {}!", out_file));
+            let mut output = create_output(&out_file);
 
-            let mut input = File::open(&in_file).or_else(|err| { error!("Could not open input file '{}'!", &in_file);
-                                                            Err(err)
-                                                           }).ok().unwrap();
+            let mut input = open_input(&in_file);
 
-            if let None = encode(&mut input, &mut output, &templates, rows) {
+            output.write_all(&header).unwrap_or_else(|_| panic!("Could not write file header!"));
+            if let None = encode(&mut input, &mut output, &templates, rows, values, &framing) {
                 panic!("Encoding error!");
             } else {
                 trace!("File processed");
@@ -162,12 +271,17 @@ fn command_encode(template_file: String,
     }
 }
 
+/// Decode each input file, returning whether any of them ended within a record.
+#[allow(clippy::too_many_arguments)]
 fn command_decode(template_file: String,
                   in_file_globs: Vec<String>,
                   out_file: String,
                   log_level: Level,
-                  rows: bool) {
-    loggerv::init_with_level(log_level).unwrap();
+                  rows: bool,
+                  values: ValuesMode,
+                  framing: Framing,
+                  partial: PartialRecord) -> bool {
+    init_logging(log_level, writes_stdout(&out_file, &in_file_globs));
 
     trace!("Decoding");
 
@@ -191,27 +305,22 @@ fn command_decode(template_file: String,
 
      trace!("Template file opened");
 
+    let mut partial_input = false;
+
     // if an output file was provided, write all output to that file.
     if out_file.len() > 0 {
         trace!("Single output file");
         info!("Outputting to {}", out_file);
 
-        let mut output_file =
-            File::create(&out_file).expect(&format!("Could not open output file '{}'!", &out_file));
-        let mut output_file = BufWriter::new(output_file);
+        let mut output_file = create_output(&out_file);
 
         trace!("{} input files to process", in_files.len());
         for in_file in in_files {
             info!("Processing input file {}", in_file);
-            let input_file =
-                File::open(&in_file).expect(&format!("Could not open input file '{}'!", &in_file));
-            let mut input = BufReader::new(input_file);
+            let mut input = open_input(&in_file);
 
-            if let None = decode(&mut input, &mut output_file, &templates, rows) {
-                panic!("Error decoding!");
-            } else {
-                trace!("File processed");
-            }
+            let ending = decode(&mut input, &mut output_file, &templates, rows, values, &framing, &partial);
+            partial_input |= decode_ended(ending, &in_file, &partial);
         }
     } else { // otherwise construct an output file for each input file.
         trace!("Multiple output files");
@@ -220,27 +329,52 @@ fn command_decode(template_file: String,
         for in_file in in_files {
             trace!("Processing input file {}", in_file);
 
-            let mut out_file = in_file.clone();
-            out_file.push_str(".csv");
+            let out_file = output_file_name(&in_file, ".csv");
             trace!("Outputting to {}", out_file);
 
-            let mut output_file =
-                File::create(&out_file).expect(&format!("Could not open output file '{}'!", &out_file));
-            let mut output_file = BufWriter::new(output_file);
+            let mut output_file = create_output(&out_file);
             trace!("Output file open");
 
-            let input_file =
-                File::open(&in_file).expect(&format!("Could not open input file '{}'!", &in_file));
-            let mut input = BufReader::new(input_file);
+            let mut input = open_input(&in_file);
             trace!("Input file open");
 
-            if let None = decode(&mut input, &mut output_file, &templates, rows) {
-                panic!("Error decoding!");
-            } else {
-                trace!("File processed");
-            }
+            let ending = decode(&mut input, &mut output_file, &templates, rows, values, &framing, &partial);
+            partial_input |= decode_ended(ending, &in_file, &partial);
         }
     }
+
+    partial_input
+}
+
+/// Check how decoding an input file ended, writing the bytes of a partial record at its
+/// end to a remainder file if asked to, and returning whether there was a partial record.
+fn decode_ended(ending: Option<Ending>, in_file: &str, partial: &PartialRecord) -> bool {
+    match ending {
+        // a decoding error exits with a failure status, distinct from that of a partial record
+        None => {
+            error!("Error decoding input file '{}'!", in_file);
+            process::exit(1);
+        },
+
+        Some(Ending::Complete) => {
+            trace!("File processed");
+            false
+        },
+
+        Some(Ending::Partial(offset, remainder)) => {
+            if partial.remainder {
+                // the remainder of stdin has no input file to be named after
+                let remainder_file = if in_file == STDIO { "stdin.remainder".to_string() } else { format!("{}.remainder", in_file) };
+                info!("Writing the {} bytes from byte offset {} to {}", remainder.len(), offset, remainder_file);
+
+                File::create(&remainder_file)
+                     .and_then(|mut file| file.write_all(&remainder))
+                     .unwrap_or_else(|_| panic!("Could not write remainder file '{}'!", remainder_file));
+            }
+
+            true
+        },
+    }
 }
 
 fn command_summary(template_file: String) {
@@ -254,35 +388,128 @@ fn command_summary(template_file: String) {
 
     trace!("Template file opened");
 
-    let mut offset_bits = 0;
+    // offsets are only known up to the first variable sized field
+    let mut offset_bits = Some(0);
 
-    println!("type,description,size bytes,size bits,byte offset, bit offset");
+    println!("type,description,size bytes,size bits,byte offset, bit offset,unit,present if");
+    summarize_templates(&templates, "", &mut offset_bits);
+}
+
+/// Print a summary line for each template, followed by the fields of any structures,
+/// with the structure's description prefixed to the field descriptions.
+fn summarize_templates(templates: &[Template], prefix: &str, offset_bits: &mut Option<usize>) {
     for template in templates {
-        let num_bits = template.typ.num_bits();
-        println!("{},{},{},{},{},{}",
-                 template.typ,
-                 template.description,
-                 num_bits / 8,
-                 num_bits,
-                 offset_bits / 8,
-                 offset_bits);
-
-        offset_bits += num_bits;
+        let num_bits = template.num_bits();
+
+        // fields with a condition are optional, and are marked with their condition
+        let present_if = template.present_if.as_ref().map(|condition| condition.to_string()).unwrap_or_default();
+
+        summarize_line(template, prefix, *offset_bits, &present_if);
+
+        match (&template.structure, &template.union) {
+            // the fields of a structure give the offsets for each of its elements
+            (Some(structure), _) => {
+                match template.count {
+                    Some(count) => {
+                        for index in 0..count {
+                            let prefix = format!("{}{}[{}].", prefix, template.description, index);
+                            summarize_templates(&structure.fields, &prefix, offset_bits);
+                        }
+                    },
+
+                    None => {
+                        // a structure repeated by a count field lists its fields once,
+                        // and their offsets depend on each record
+                        if template.count_field.is_some() {
+                            *offset_bits = None;
+                        }
+
+                        let prefix = format!("{}{}.", prefix, template.description);
+                        summarize_templates(&structure.fields, &prefix, offset_bits);
+                    },
+                }
+            },
+
+            // each branch of a union is listed with the condition choosing it,
+            // and its fields start at the offset of the union
+            (None, Some(union)) => {
+                for branch in union.branches.iter() {
+                    let mut branch_offset_bits = *offset_bits;
+
+                    let branch_template = Template::structure(branch.structure.name.clone(),
+                                                              branch.structure.fields.clone(),
+                                                              template.description.clone(),
+                                                              None);
+                    summarize_line(&branch_template, prefix, branch_offset_bits, &union.condition(branch));
+
+                    let branch_prefix = format!("{}{}.", prefix, template.description);
+                    summarize_templates(&branch.structure.fields, &branch_prefix, &mut branch_offset_bits);
+                }
+
+                *offset_bits = match *offset_bits {
+                    Some(offset_bits) if !template.is_variable() => Some(offset_bits + num_bits),
+                    _ => None,
+                };
+            },
+
+            (None, None) => {
+                *offset_bits = match *offset_bits {
+                    Some(offset_bits) if !template.is_variable() => Some(offset_bits + num_bits),
+                    _ => None,
+                };
+            },
+        }
+
+        // the offsets of later fields depend on whether an optional field is present
+        if template.present_if.is_some() {
+            *offset_bits = None;
+        }
     }
 }
 
+/// Print the summary line of a single template, giving its size and its offset, if known.
+fn summarize_line(template: &Template, prefix: &str, offset_bits: Option<usize>, present_if: &str) {
+    // arrays report their total size, and the offset of their first element
+    let num_bits = template.num_bits();
+
+    let (size_bytes, size_bits) = if template.is_variable() {
+        ("variable".to_string(), "variable".to_string())
+    } else {
+        ((num_bits / 8).to_string(), num_bits.to_string())
+    };
+
+    let (byte_offset, bit_offset) = match offset_bits {
+        Some(offset_bits) => ((offset_bits / 8).to_string(), offset_bits.to_string()),
+        None => ("".to_string(), "".to_string()),
+    };
+
+    println!("{},{}{},{},{},{},{},{},{}",
+             template.type_name(),
+             prefix,
+             template.description,
+             size_bytes,
+             size_bits,
+             byte_offset,
+             bit_offset,
+             template.unit.clone().unwrap_or_default(),
+             present_if);
+}
+
 fn main() {
     let opt = Opt::from_args();
 
     match opt {
         // Encoding csv into binary
-        Opt::Encode { template_file, in_file_globs, out_file, log_level, rows } => {
-            command_encode(template_file, in_file_globs, out_file, log_level, rows);
+        Opt::Encode { template_file, in_file_globs, out_file, log_level, rows, values, framing, header_file } => {
+            command_encode(template_file, in_file_globs, out_file, log_level, rows, values, framing, header_file);
         },
 
         // Decoding binary into csv
-        Opt::Decode { template_file, in_file_globs, out_file, log_level, rows } => {
-            command_decode(template_file, in_file_globs, out_file, log_level, rows);
+        // an input ending within a record gives its own exit status, once the output is written
+        Opt::Decode { template_file, in_file_globs, out_file, log_level, rows, values, framing, partial } => {
+            if command_decode(template_file, in_file_globs, out_file, log_level, rows, values, framing, partial) {
+                process::exit(PARTIAL_RECORD_STATUS);
+            }
         },
 
         Opt::Summary { template_file } => {
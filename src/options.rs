@@ -0,0 +1,32 @@
+use structopt::StructOpt;
+
+use crate::types::SyncMarker;
+
+
+/// How records are laid out within a file, beyond their templates: a file header
+/// before the first record, a sync marker before each record, a fixed stride from
+/// the start of one record to the next, and a limit on the number of records.
+#[derive(Eq, PartialEq, Debug, Clone, Default, StructOpt)]
+pub struct Framing {
+    #[structopt(long="sync", help="Sync marker before each record, in hex, such as 1ACFFC1D")]
+    pub sync: Option<SyncMarker>,
+
+    #[structopt(long="skip", default_value="0", help="Bytes of file header before the first record")]
+    pub skip: u64,
+
+    #[structopt(long="stride", help="Bytes from the start of each record to the next, padding records that are shorter")]
+    pub stride: Option<usize>,
+
+    #[structopt(long="count", help="Maximum number of records")]
+    pub count: Option<usize>,
+}
+
+/// How to handle a partial record at the end of the input, which is always reported.
+#[derive(Eq, PartialEq, Debug, Clone, Default, StructOpt)]
+pub struct PartialRecord {
+    #[structopt(long="partial", help="Decode a partial record at the end of the input as far as it goes")]
+    pub decode: bool,
+
+    #[structopt(long="remainder", help="Write the bytes of a partial record at the end of the input to a .remainder file")]
+    pub remainder: bool,
+}
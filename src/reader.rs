@@ -0,0 +1,138 @@
+use std::collections::VecDeque;
+use std::io::{Read, Result};
+
+
+/// A reader over the input being decoded, which keeps track of its position
+/// in the input and whether the end of the input has been reached. The bytes
+/// of the current record are kept, so checksums can be computed over them,
+/// and bytes can be put back to be read again when resynchronizing.
+pub struct InputReader<R> {
+    input: R,
+    position: u64,
+    at_end: bool,
+    record: Vec<u8>,
+    unread: VecDeque<u8>,
+}
+
+impl<R: Read> InputReader<R> {
+    pub fn new(input: R) -> InputReader<R> {
+        InputReader { input, position: 0, at_end: false, record: vec!(), unread: VecDeque::new() }
+    }
+
+    /// Start a new record, discarding the bytes of the last record.
+    pub fn start_record(&mut self) {
+        self.record.clear();
+    }
+
+    /// The bytes read since the start of the current record.
+    pub fn record(&self) -> &[u8] {
+        &self.record
+    }
+
+    /// Put bytes back to be read again before the rest of the input, moving
+    /// the position back to the first of them.
+    pub fn unread(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter().rev() {
+            self.unread.push_front(*byte);
+        }
+
+        self.position -= bytes.len() as u64;
+        self.at_end = false;
+    }
+
+    /// Read up to the end of the next occurrence of the pattern, returning the
+    /// position it starts at, or None if the input ends before it is found.
+    pub fn find(&mut self, pattern: &[u8]) -> Result<Option<u64>> {
+        let mut window: VecDeque<u8> = VecDeque::with_capacity(pattern.len());
+        let mut byte = [0; 1];
+
+        while !window.iter().eq(pattern.iter()) {
+            if self.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+
+            if window.len() == pattern.len() {
+                window.pop_front();
+            }
+            window.push_back(byte[0]);
+        }
+
+        Ok(Some(self.position - pattern.len() as u64))
+    }
+
+    /// The number of bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Whether a read has found the end of the input.
+    pub fn at_end(&self) -> bool {
+        self.at_end
+    }
+}
+
+impl<R: Read> Read for InputReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        // bytes that were put back are read before the rest of the input
+        if !self.unread.is_empty() {
+            let num_read = self.unread.read(buf)?;
+
+            self.position += num_read as u64;
+            self.record.extend_from_slice(&buf[..num_read]);
+
+            return Ok(num_read);
+        }
+
+        let num_read = self.input.read(buf)?;
+
+        if num_read == 0 && !buf.is_empty() {
+            self.at_end = true;
+        }
+
+        self.position += num_read as u64;
+        self.record.extend_from_slice(&buf[..num_read]);
+
+        Ok(num_read)
+    }
+}
+
+#[test]
+fn test_input_reader() {
+    let mut input: &[u8] = &[1, 2, 3];
+    let mut reader = InputReader::new(&mut input);
+
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes).unwrap();
+    assert_eq!(reader.position(), 2);
+    assert!(!reader.at_end());
+
+    assert!(reader.read_exact(&mut bytes).is_err());
+    assert_eq!(reader.position(), 3);
+    assert!(reader.at_end());
+    assert_eq!(reader.record(), &[1, 2, 3]);
+
+    reader.start_record();
+    assert!(reader.record().is_empty());
+}
+
+#[test]
+fn test_input_reader_find() {
+    let mut input: &[u8] = &[0, 0xEB, 0xEB, 0x90, 1, 2, 0xEB];
+    let mut reader = InputReader::new(&mut input);
+
+    assert_eq!(reader.find(&[0xEB, 0x90]).unwrap(), Some(2));
+    assert_eq!(reader.position(), 4);
+
+    // bytes put back are read again
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes).unwrap();
+    reader.unread(&bytes);
+    assert_eq!(reader.position(), 4);
+
+    reader.start_record();
+    assert_eq!(reader.find(&[2, 0xEB]).unwrap(), Some(5));
+    assert_eq!(reader.record(), &[1, 2, 0xEB]);
+
+    assert_eq!(reader.find(&[0xEB, 0x90]).unwrap(), None);
+    assert!(reader.at_end());
+}
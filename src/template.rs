@@ -1,22 +1,187 @@
 use std::fmt;
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
 
 use crate::types::*;
+use crate::enumeration::*;
+use crate::calibration::*;
+use crate::condition::Condition;
+use crate::checksum::Span;
+use crate::layout::*;
 
 
 /// A template gives enough information to decode a field from a binary file,
 /// providing the type information used for decoding as well as a description of the
 /// field.
-#[derive(Eq, PartialEq, Debug, Clone, Deserialize)]
+///
+/// A template may describe an array of fields, given by a count after the
+/// type such as 'uint16_be[32]', in which case the type is the type of each element.
+/// The count may instead name an earlier field, such as 'uint16_be[numEntries]',
+/// in which case the number of elements is the value of that field in each record.
+///
+/// Blobs, such as 'hex[packetLen+1]', are a single field whose length in bytes
+/// is given by an earlier field.
+///
+/// Padding fields are not decoded, and are filled with the template's fill
+/// value when encoding. Constant fields, such as sync words, are likewise not
+/// decoded, but are checked against their constant, which is written out when
+/// encoding.
+///
+/// Checksum fields, such as 'crc16_ccitt_be', are computed when encoding and
+/// checked when decoding, over the bytes of the record given by their span,
+/// or every byte of the record before them without a span.
+///
+/// Integer fields may have an enumeration, in which case they are decoded
+/// as labels rather than numbers, and numeric fields may have a calibration
+/// converting them into engineering units, or a time scale converting them
+/// into timestamps.
+///
+/// A template may also be a structure, given as 'struct:file.csv', whose fields
+/// come from another template file. Structures are expanded into their fields,
+/// with the structure's description prefixed to each field's description.
+///
+/// Any template may have a 'present_if' condition on earlier fields, such as
+/// 'secHeaderFlag == 1', in which case it is only present in records where
+/// the condition holds.
+///
+/// A template may also be a union, given as 'union:branches.csv[apid]', which
+/// lists a structure for each value of an earlier field. Each record has the
+/// fields of the structure chosen by that field's value.
+///
+/// The last template may give the length of the whole record from an earlier
+/// field, such as 'record_length[packetLen+7]', in which case any bytes of the
+/// record after the fields before it are written out in hex under its
+/// description, or skipped if it has no description.
+#[derive(PartialEq, Debug, Clone, Deserialize)]
 pub struct Template {
     pub typ: FieldType,
     pub description: String,
+    pub count: Option<usize>,
+    pub count_field: Option<FieldRef>,
+    pub length_field: Option<FieldRef>,
+    pub fill: u128,
+    pub constant: Option<String>,
+    pub span: Option<Span>,
+    pub enumeration: Option<Enumeration>,
+    pub calibration: Option<Calibration>,
+    pub unit: Option<String>,
+    pub timestamp: Option<TimeScale>,
+    pub present_if: Option<Condition>,
+    pub structure: Option<Structure>,
+    pub union: Option<Union>,
+    pub record_length: Option<FieldRef>,
+}
+
+/// A reference to an earlier field of a record, whose value gives the count or
+/// length of a later field once the adjustment is added to it, such as 'packetLen+1'.
+#[derive(Eq, PartialEq, Debug, Clone, Deserialize)]
+pub struct FieldRef {
+    pub field: String,
+    pub adjustment: i128,
+}
+
+impl FieldRef {
+    pub fn new(field: &str) -> FieldRef {
+        FieldRef { field: field.to_string(), adjustment: 0 }
+    }
+
+    /// Parse a field name, optionally followed by a number to add or subtract.
+    pub fn parse(text: &str) -> FieldRef {
+        lazy_static! {
+          static ref ADJUSTMENT_REGEX: Regex =
+              Regex::new(r"^(.+?)\s*([+-])\s*(\d+)$").unwrap();
+        }
+
+        let text = text.trim();
+
+        match ADJUSTMENT_REGEX.captures(text) {
+            Some(matches) => {
+                let amount = matches[3].parse::<i128>().unwrap_or(0);
+                let adjustment = if &matches[2] == "-" { -amount } else { amount };
+                FieldRef { field: matches[1].to_string(), adjustment }
+            },
+
+            None => FieldRef::new(text),
+        }
+    }
+}
+
+impl fmt::Display for FieldRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.adjustment {
+            0 => write!(f, "{}", self.field),
+            adjustment if adjustment > 0 => write!(f, "{}+{}", self.field, adjustment),
+            adjustment => write!(f, "{}{}", self.field, adjustment),
+        }
+    }
+}
+
+/// The fields of a structure, along with the name of the template file they were read from.
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+pub struct Structure {
+    pub name: String,
+    pub fields: Vec<Template>,
+}
+
+/// The branches of a union, along with the name of the file listing them, and
+/// the earlier field whose value chooses between them.
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+pub struct Union {
+    pub name: String,
+    pub discriminator: FieldRef,
+    pub branches: Vec<Branch>,
+}
+
+/// A branch of a union, chosen by the given value, or by any value not listed
+/// if it is the default branch.
+#[derive(PartialEq, Debug, Clone, Deserialize)]
+pub struct Branch {
+    pub value: Option<i128>,
+    pub structure: Structure,
+}
+
+impl Union {
+    /// The branch chosen by a value of the discriminator, falling back to the
+    /// default branch, or None if there is no branch for the value.
+    pub fn branch(&self, value: i128) -> Option<&Branch> {
+        self.branches.iter()
+                     .find(|branch| branch.value == Some(value))
+                     .or_else(|| self.branches.iter().find(|branch| branch.value.is_none()))
+    }
+
+    /// The condition under which a branch is chosen, such as 'apid == 3'.
+    pub fn condition(&self, branch: &Branch) -> String {
+        let field = &self.discriminator.field;
+
+        match branch.value {
+            Some(value) => format!("{} == {}", field, value),
+
+            None => {
+                let others: Vec<String> = self.branches.iter()
+                                                       .filter_map(|branch| branch.value)
+                                                       .map(|value| format!("{} != {}", field, value))
+                                                       .collect();
+
+                if others.is_empty() { "true".to_string() } else { others.join(" && ") }
+            },
+        }
+    }
 }
 
 impl HasNumBits for Template {
     fn num_bits(&self) -> NumBits {
-        self.typ.num_bits()
+        match (&self.structure, &self.union) {
+            (Some(structure), _) => structure.fields.num_bits() * self.count.unwrap_or(1),
+
+            // a union only has a size when each of its branches has the same size
+            (None, Some(union)) => union.branches.first().map(|branch| branch.structure.fields.num_bits()).unwrap_or(0),
+
+            (None, None) => self.typ.num_bits() * self.count.unwrap_or(1),
+        }
     }
 }
 
@@ -24,6 +189,9 @@ impl HasNumBits for Template {
 pub enum TemplateError {
     LineNumber(usize),
     RecordError(usize),
+    FileError(String),
+    Cycle(String),
+    InFile(String, Box<TemplateError>),
 }
 
 impl fmt::Display for TemplateError {
@@ -36,23 +204,185 @@ impl fmt::Display for TemplateError {
             TemplateError::RecordError(line_number) => {
                 write!(f, "Error reading record on line {}", line_number)
             }
+
+            TemplateError::FileError(file_name) => {
+                write!(f, "Could not open template file '{}'", file_name)
+            }
+
+            TemplateError::Cycle(file_name) => {
+                write!(f, "Template file '{}' includes itself", file_name)
+            }
+
+            TemplateError::InFile(file_name, error) => {
+                write!(f, "{} in template file '{}'", error, file_name)
+            }
         }
     }
 }
 
 impl Template {
     pub fn new(typ: FieldType, descr: String) -> Template {
-        Template { typ: typ, description: descr }
+        Template { typ, description: descr, count: None, count_field: None, length_field: None, fill: 0, constant: None, span: None, enumeration: None, calibration: None, unit: None, timestamp: None, present_if: None, structure: None, union: None, record_length: None }
+    }
+
+    pub fn array(typ: FieldType, descr: String, count: usize) -> Template {
+        Template { count: Some(count), ..Template::new(typ, descr) }
+    }
+
+    /// Create a structure from the fields of another template.
+    pub fn structure(name: String, fields: Vec<Template>, descr: String, count: Option<usize>) -> Template {
+        Template { count, structure: Some(Structure { name, fields }), ..Template::new(FieldType::Struct, descr) }
+    }
+
+    /// Create a union of the given branches, chosen by the value of an earlier field.
+    pub fn union(name: String, discriminator: FieldRef, branches: Vec<Branch>, descr: String) -> Template {
+        Template { union: Some(Union { name, discriminator, branches }), ..Template::new(FieldType::Struct, descr) }
+    }
+
+    /// Whether this template's size is only known when it is read, such as a varint,
+    /// a field repeated by a count field, a structure containing one, or a union
+    /// whose branches differ in size.
+    pub fn is_variable(&self) -> bool {
+        if self.count_field.is_some() {
+            return true;
+        }
+
+        match (&self.structure, &self.union) {
+            (Some(structure), _) => structure.fields.iter().any(|field| field.is_variable()),
+
+            (None, Some(union)) => {
+                union.branches.iter().any(|branch| branch.structure.fields.iter().any(|field| field.is_variable())) ||
+                union.branches.iter().any(|branch| branch.structure.fields.num_bits() != self.num_bits())
+            },
+
+            (None, None) => self.typ.is_variable(),
+        }
+    }
+
+    /// Whether this template's values are converted when decoding, either
+    /// with an enumeration, a calibration, or a time scale.
+    pub fn is_converted(&self) -> bool {
+        self.enumeration.is_some() || self.calibration.is_some() || self.timestamp.is_some()
+    }
+
+    /// Whether this template is left out of decoded output, and filled
+    /// in automatically when encoding, such as padding or a constant.
+    pub fn is_hidden(&self) -> bool {
+        self.typ.is_pad() || self.constant.is_some() || (self.record_length.is_some() && self.description.is_empty())
+    }
+
+    /// Parse a template type, which is a field type optionally followed by
+    /// an array count, such as 'uint16_be' or 'uint4_be:16[4]'.
+    pub fn parse_type(type_str: &str) -> Result<(FieldType, Option<usize>), FieldParseError> {
+        lazy_static! {
+          static ref ARRAY_REGEX: Regex =
+              Regex::new(r"^(.*)\[(\d+)\]$").unwrap();
+        }
+
+        let type_str = type_str.trim();
+
+        match ARRAY_REGEX.captures(type_str) {
+            Some(matches) => {
+                let typ = FieldType::from_str(&matches[1])?;
+                let count = matches[2].parse::<usize>().map_err(|_| FieldParseError(()))?;
+
+                if count == 0 {
+                    error!("Array type '{}' must have at least one element", type_str);
+                    return Err(FieldParseError(()));
+                }
+
+                match typ {
+                    // an array of characters, like 'char[16]', is a single string
+                    FieldType::Str(1, padding, encoding) => {
+                        Ok((FieldType::Str(count, padding, encoding), None))
+                    },
+
+                    _ => Ok((typ, Some(count))),
+                }
+            },
+
+            None => Ok((FieldType::from_str(type_str)?, None)),
+        }
+    }
+
+    /// The type of this template as it would be written in a template file.
+    pub fn type_name(&self) -> String {
+        if let Some(record_length) = &self.record_length {
+            return format!("record_length[{}]", record_length);
+        }
+
+        let typ = match (&self.structure, &self.union) {
+            (Some(structure), _) => format!("struct:{}", structure.name),
+            (None, Some(union)) => format!("union:{}[{}]", union.name, union.discriminator),
+            (None, None) => self.typ.to_string(),
+        };
+
+        match (self.count, &self.count_field, &self.length_field) {
+            (Some(count), _, _) => format!("{}[{}]", typ, count),
+            (None, Some(field_ref), _) | (None, None, Some(field_ref)) => format!("{}[{}]", typ, field_ref),
+            (None, None, None) => typ,
+        }
     }
 
     pub fn read_templates(template_file: &String) -> Result<Vec<Template>, TemplateError> {
+        let mut including = vec!();
+        Template::read_template_file(Path::new(template_file), &mut including)
+    }
+
+    /// Read a template file, reading any structures it refers to. The files
+    /// currently being read are kept to detect a template that includes itself.
+    fn read_template_file(template_file: &Path, including: &mut Vec<PathBuf>) -> Result<Vec<Template>, TemplateError> {
+        lazy_static! {
+          static ref STRUCT_REGEX: Regex =
+              Regex::new(r"^struct:(.+?)(?:\[(\d+)\])?$").unwrap();
+
+          static ref UNION_REGEX: Regex =
+              Regex::new(r"^union:([^\[\]]+)$").unwrap();
+
+          // a count naming a field, rather than a number of elements
+          static ref COUNT_FIELD_REGEX: Regex =
+              Regex::new(r"^(.+?)\[\s*([^\]\d\s][^\]]*?)\s*\]$").unwrap();
+        }
+
         let mut templates: Vec<Template> = vec!();
 
-        let template: File =
-            File::open(&template_file).expect(&format!("Could not open template file '{}'!", &template_file));
+        let file_name = template_file.display().to_string();
+
+        // the length of a record can only be given by the template file describing the whole record
+        let top_level = including.is_empty();
+
+        let full_path = template_file.canonicalize().map_err(|_| {
+            error!("Could not open template file '{}'!", file_name);
+            TemplateError::FileError(file_name.clone())
+        })?;
+
+        if including.contains(&full_path) {
+            error!("Template file '{}' includes itself!", file_name);
+            return Err(TemplateError::Cycle(file_name));
+        }
+
+        let template: File = File::open(&full_path).map_err(|_| TemplateError::FileError(file_name.clone()))?;
 
         let mut lines = csv::Reader::from_reader(&template);
-        info!("Opened Template File {}", &template_file);
+        info!("Opened Template File {}", file_name);
+
+        including.push(full_path);
+
+        // optional columns are found by name in the header
+        let headers = lines.headers().map_err(|_| TemplateError::RecordError(1))?.clone();
+        let fill_column = find_column(&headers, "fill");
+        let const_column = find_column(&headers, "const");
+        let span_column = find_column(&headers, "span");
+        let enum_column = find_column(&headers, "enum");
+        let scale_column = find_column(&headers, "scale");
+        let offset_column = find_column(&headers, "offset");
+        let poly_column = find_column(&headers, "poly");
+        let unit_column = find_column(&headers, "unit");
+        let time_column = find_column(&headers, "time");
+        let present_column = find_column(&headers, "present_if");
+
+        // enumeration and structure files are found relative to the template file
+        let template_dir = template_file.parent().unwrap_or(Path::new(""));
 
         // line number, assuming header
         let mut line_number: usize = 2;
@@ -60,13 +390,310 @@ impl Template {
         // Decode template from input file.
         for record in lines.records() {
             let rec = record.map_err(|_| TemplateError::RecordError(line_number))?;
-            let typ = FieldType::from_str(&rec[0]).map_err(|_| TemplateError::LineNumber(line_number))?;
             let desc = rec[1].to_string().trim().to_string();
 
+            if templates.last().map(|template| template.record_length.is_some()).unwrap_or(false) {
+                error!("The record length must be given by the last line of the template file");
+                return Err(TemplateError::LineNumber(line_number));
+            }
+
+            // a field repeated by an earlier field has the count field removed from its type
+            let (type_str, count_field) = match COUNT_FIELD_REGEX.captures(rec[0].trim()) {
+                Some(matches) => (matches[1].to_string(), Some(FieldRef::parse(&matches[2]))),
+                None => (rec[0].trim().to_string(), None),
+            };
+
+            let present_if = match optional_column(&rec, present_column) {
+                Some(condition) => Some(Condition::parse(condition).ok_or(TemplateError::LineNumber(line_number))?),
+                None => None,
+            };
+
+            // the length of the whole record is given by an earlier field, with any bytes
+            // after the fields before it kept as a hex blob, or skipped without a description
+            if type_str == "record_length" {
+                let record_length = match count_field {
+                    Some(record_length) if top_level => record_length,
+
+                    Some(_) => {
+                        error!("The record length can not be given within a structure or union");
+                        return Err(TemplateError::LineNumber(line_number));
+                    },
+
+                    None => {
+                        error!("The record length must give the field holding it, such as 'record_length[packetLen+7]'");
+                        return Err(TemplateError::LineNumber(line_number));
+                    },
+                };
+
+                templates.push(Template { record_length: Some(record_length),
+                                          present_if,
+                                          ..Template::new(FieldType::Blob(BlobFormat::Hex), desc) });
+
+                line_number += 1;
+                continue;
+            }
+
+            // unions list their branches in their own file, and are chosen
+            // between by the field given in place of a count
+            if let Some(matches) = UNION_REGEX.captures(&type_str) {
+                let name = matches[1].trim().to_string();
+
+                let discriminator = match count_field {
+                    Some(discriminator) => discriminator,
+
+                    None => {
+                        error!("Union '{}' must give the field choosing its branch, such as 'union:{}[type]'", desc, name);
+                        return Err(TemplateError::LineNumber(line_number));
+                    },
+                };
+
+                if discriminator.adjustment != 0 {
+                    error!("Union '{}' is chosen by the value of '{}', which can not be adjusted", desc, discriminator.field);
+                    return Err(TemplateError::LineNumber(line_number));
+                }
+
+                let branches = Template::read_union_file(&template_dir.join(&name), including)
+                                        .map_err(|err| TemplateError::InFile(name.clone(), Box::new(err)))?;
+
+                templates.push(Template { present_if, ..Template::union(name, discriminator, branches, desc) });
+
+                line_number += 1;
+                continue;
+            }
+
+            // structures are read from their own template file
+            if let Some(matches) = STRUCT_REGEX.captures(&type_str) {
+                let name = matches[1].trim().to_string();
+
+                let count = match matches.get(2) {
+                    Some(count) => {
+                        match count.as_str().parse::<usize>() {
+                            Ok(count) if count > 0 => Some(count),
+
+                            _ => {
+                                error!("Structure '{}' must have at least one element", desc);
+                                return Err(TemplateError::LineNumber(line_number));
+                            },
+                        }
+                    },
+
+                    None => None,
+                };
+
+                let fields = Template::read_template_file(&template_dir.join(&name), including)
+                                      .map_err(|err| TemplateError::InFile(name.clone(), Box::new(err)))?;
+
+                if count.is_some() && count_field.is_some() {
+                    error!("Structure '{}' can not have both a count and a count field", desc);
+                    return Err(TemplateError::LineNumber(line_number));
+                }
+
+                templates.push(Template { count_field, present_if, ..Template::structure(name, fields, desc, count) });
+
+                line_number += 1;
+                continue;
+            }
+
+            let (typ, count) = Template::parse_type(&type_str).map_err(|_| TemplateError::LineNumber(line_number))?;
+
+            if count.is_some() && count_field.is_some() {
+                error!("Field '{}' can not have both a count and a count field", desc);
+                return Err(TemplateError::LineNumber(line_number));
+            }
+
+            // a blob is a single field, whose length is given by the field named after its type
+            let (count_field, length_field) = match (typ, count, count_field) {
+                (FieldType::Blob(_), None, Some(length_field)) => (None, Some(length_field)),
+
+                (FieldType::Blob(_), _, _) => {
+                    error!("Blob '{}' must give the field holding its length, such as '{}[length]'", desc, typ);
+                    return Err(TemplateError::LineNumber(line_number));
+                },
+
+                (_, _, count_field) => (count_field, None),
+            };
+
+            let fill = match optional_column(&rec, fill_column) {
+                Some(fill_str) => {
+                    let fill = parse_integer(fill_str).ok_or(TemplateError::LineNumber(line_number))?;
+
+                    // the fill value must fit within the padding
+                    if typ.num_bits() < 128 && (fill >> typ.num_bits()) != 0 {
+                        error!("Fill value {} does not fit in {} bits", fill_str, typ.num_bits());
+                        return Err(TemplateError::LineNumber(line_number));
+                    }
+
+                    fill
+                },
+
+                None => 0,
+            };
+
+            // an enumeration is either given inline, or as the name of a file
+            let enumeration = match optional_column(&rec, enum_column) {
+                Some(enum_str) => {
+                    match typ {
+                        FieldType::Int(_, _, _) | FieldType::Uint(_, _, _) | FieldType::Bcd(_, _, _, _) => {},
+
+                        _ => {
+                            error!("Enumeration given for field '{}', which is not an integer", desc);
+                            return Err(TemplateError::LineNumber(line_number));
+                        },
+                    }
+
+                    let enumeration = if enum_str.contains('=') {
+                        Enumeration::parse(enum_str)
+                    } else {
+                        Enumeration::read_file(&template_dir.join(enum_str))
+                    };
+
+                    Some(enumeration.ok_or(TemplateError::LineNumber(line_number))?)
+                },
+
+                None => None,
+            };
+
+            // a calibration is either a scale and offset, or a polynomial
+            let scale = optional_column(&rec, scale_column);
+            let offset = optional_column(&rec, offset_column);
+            let poly = optional_column(&rec, poly_column);
+
+            let calibration = match (scale, offset, poly) {
+                (None, None, None) => None,
+
+                (None, None, Some(poly_str)) => {
+                    Some(Calibration::parse_polynomial(poly_str).ok_or(TemplateError::LineNumber(line_number))?)
+                },
+
+                (_, _, None) => {
+                    let scale = parse_number(scale.unwrap_or("1")).ok_or(TemplateError::LineNumber(line_number))?;
+                    let offset = parse_number(offset.unwrap_or("0")).ok_or(TemplateError::LineNumber(line_number))?;
+                    Some(Calibration::linear(scale, offset))
+                },
+
+                (_, _, Some(_)) => {
+                    error!("Field '{}' can not have both a polynomial and a scale or offset", desc);
+                    return Err(TemplateError::LineNumber(line_number));
+                },
+            };
+
+            if calibration.is_some() {
+                match typ {
+                    FieldType::Int(_, _, _) | FieldType::Uint(_, _, _) | FieldType::Bcd(_, _, _, _) |
+                    FieldType::Float(_) | FieldType::Double(_) |
+                    FieldType::Half(_) | FieldType::BFloat16(_) => {},
+
+                    _ => {
+                        error!("Calibration given for field '{}', which is not a number", desc);
+                        return Err(TemplateError::LineNumber(line_number));
+                    },
+                }
+
+                if enumeration.is_some() {
+                    error!("Field '{}' can not have both an enumeration and a calibration", desc);
+                    return Err(TemplateError::LineNumber(line_number));
+                }
+            }
+
+            // a time scale gives the unit and epoch of a timestamp
+            let timestamp = match optional_column(&rec, time_column) {
+                Some(time_str) => {
+                    match typ {
+                        FieldType::Int(_, _, _) | FieldType::Uint(_, _, _) |
+                        FieldType::Float(_) | FieldType::Double(_) => {},
+
+                        _ => {
+                            error!("Time given for field '{}', which is not an integer or float", desc);
+                            return Err(TemplateError::LineNumber(line_number));
+                        },
+                    }
+
+                    if enumeration.is_some() || calibration.is_some() {
+                        error!("Field '{}' can not have a time along with an enumeration or calibration", desc);
+                        return Err(TemplateError::LineNumber(line_number));
+                    }
+
+                    Some(TimeScale::parse(time_str).ok_or(TemplateError::LineNumber(line_number))?)
+                },
+
+                None => None,
+            };
+
+            // a constant, such as a magic number or sync word, is kept as the text
+            // of its value, which is compared against the value of each record
+            let constant = match optional_column(&rec, const_column) {
+                Some(const_str) => {
+                    let constant = match typ {
+                        FieldType::Int(num_bits, _, _) => {
+                            parse_signed(const_str).filter(|value| num_bits >= 128 || (value >> (num_bits - 1)) == 0 || (value >> (num_bits - 1)) == -1)
+                                                   .map(|value| value.to_string())
+                        },
+
+                        FieldType::Uint(num_bits, _, _) => {
+                            parse_integer(const_str).filter(|value| num_bits >= 128 || (value >> num_bits) == 0)
+                                                    .map(|value| value.to_string())
+                        },
+
+                        FieldType::Str(num_bytes, _, _) => Some(const_str.to_string()).filter(|text| text.len() <= num_bytes),
+
+                        _ => {
+                            error!("Constant given for field '{}', which is not an integer or string", desc);
+                            return Err(TemplateError::LineNumber(line_number));
+                        },
+                    };
+
+                    if enumeration.is_some() || calibration.is_some() || timestamp.is_some() {
+                        error!("Field '{}' can not have a constant along with an enumeration, calibration, or time", desc);
+                        return Err(TemplateError::LineNumber(line_number));
+                    }
+
+                    match constant {
+                        Some(constant) => Some(constant),
+
+                        None => {
+                            error!("Constant {} does not fit in field '{}' of type {}", const_str, desc, typ);
+                            return Err(TemplateError::LineNumber(line_number));
+                        },
+                    }
+                },
+
+                None => None,
+            };
+
+            // the span of a checksum is either a range of bytes or of fields
+            let span = match optional_column(&rec, span_column) {
+                Some(span_str) => {
+                    if !typ.is_checksum() {
+                        error!("Span given for field '{}', which is not a checksum", desc);
+                        return Err(TemplateError::LineNumber(line_number));
+                    }
+
+                    Some(Span::parse(span_str).ok_or(TemplateError::LineNumber(line_number))?)
+                },
+
+                None => None,
+            };
+
+            let unit = optional_column(&rec, unit_column).map(|unit| unit.to_string());
+
             let template: Template =
                 Template {
                     typ: typ,
                     description: desc,
+                    count,
+                    count_field,
+                    length_field,
+                    fill,
+                    constant,
+                    span,
+                    enumeration,
+                    calibration,
+                    unit,
+                    timestamp,
+                    present_if,
+                    structure: None,
+                    union: None,
+                    record_length: None,
                 };
 
             templates.push(template);
@@ -74,7 +701,340 @@ impl Template {
             line_number += 1;
         }
 
+        including.pop();
+
         return Ok(templates);
     }
+
+    /// Read the branches of a union from a csv file with a 'value' and 'template'
+    /// column, where a value of '*' gives the default branch and a blank template
+    /// gives a branch with no fields.
+    fn read_union_file(union_file: &Path, including: &mut Vec<PathBuf>) -> Result<Vec<Branch>, TemplateError> {
+        let file_name = union_file.display().to_string();
+
+        let file = File::open(union_file).map_err(|_| {
+            error!("Could not open union file '{}'!", file_name);
+            TemplateError::FileError(file_name.clone())
+        })?;
+
+        let mut lines = csv::Reader::from_reader(file);
+        info!("Opened Union File {}", file_name);
+
+        // branch templates are found relative to the union file
+        let union_dir = union_file.parent().unwrap_or(Path::new(""));
+
+        let mut branches: Vec<Branch> = vec!();
+
+        // the columns of each field, and whether they are converted, as fields
+        // with the same description in different branches share a column
+        let mut converted_columns: HashMap<String, bool> = HashMap::new();
+
+        let mut line_number: usize = 2;
+
+        for record in lines.records() {
+            let rec = record.map_err(|_| TemplateError::RecordError(line_number))?;
+
+            if rec.len() < 2 {
+                error!("Union file '{}' should have a value and template on each line", file_name);
+                return Err(TemplateError::LineNumber(line_number));
+            }
+
+            let value = match rec[0].trim() {
+                "*" => None,
+
+                value => match parse_signed(value) {
+                    Some(value) => Some(value),
+
+                    None => {
+                        error!("Union value '{}' is not an integer", value);
+                        return Err(TemplateError::LineNumber(line_number));
+                    },
+                },
+            };
+
+            if branches.iter().any(|branch| branch.value == value) {
+                error!("Union file '{}' lists the branch for {} more than once", file_name, rec[0].trim());
+                return Err(TemplateError::LineNumber(line_number));
+            }
+
+            let name = rec[1].trim().to_string();
+
+            let fields = if name.is_empty() {
+                vec!()
+            } else {
+                Template::read_template_file(&union_dir.join(&name), including)
+                         .map_err(|err| TemplateError::InFile(name.clone(), Box::new(err)))?
+            };
+
+            for element in record_columns(&fields) {
+                let converted = element.template.is_converted();
+
+                if *converted_columns.entry(element.column.clone()).or_insert(converted) != converted {
+                    error!("Field '{}' is converted in some branches of union '{}' but not others", element.column, file_name);
+                    return Err(TemplateError::LineNumber(line_number));
+                }
+            }
+
+            branches.push(Branch { value, structure: Structure { name, fields } });
+
+            line_number += 1;
+        }
+
+        Ok(branches)
+    }
 }
 
+
+/// Find the index of an optional template column by name.
+fn find_column(headers: &csv::StringRecord, name: &str) -> Option<usize> {
+    headers.iter().position(|header| header.trim().eq_ignore_ascii_case(name))
+}
+
+/// Get the entry in an optional column of a record, if the column
+/// exists and the entry is not blank.
+fn optional_column(rec: &csv::StringRecord, column: Option<usize>) -> Option<&str> {
+    column.and_then(|index| rec.get(index))
+          .map(|entry| entry.trim())
+          .filter(|entry| !entry.is_empty())
+}
+
+/// Parse an integer given either in decimal or in hex with a '0x' prefix.
+pub fn parse_integer(text: &str) -> Option<u128> {
+    let text = text.trim();
+
+    if text.starts_with("0x") || text.starts_with("0X") {
+        u128::from_str_radix(&text[2..], 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+/// Parse a finite decimal number, such as a scale or offset.
+fn parse_number(text: &str) -> Option<f64> {
+    let number = text.trim().parse::<f64>().ok().filter(|number| number.is_finite());
+
+    if number.is_none() {
+        error!("'{}' is not a number", text.trim());
+    }
+
+    number
+}
+
+#[test]
+fn test_template_parse_type() {
+    let endianness = Endianness::Big;
+
+    assert_eq!(Template::parse_type("uint16_be"), Ok((FieldType::u16(endianness), None)));
+    assert_eq!(Template::parse_type("uint16_be[32]"), Ok((FieldType::u16(endianness), Some(32))));
+    assert_eq!(Template::parse_type(" uint4_be:16[4]"),
+               Ok((FieldType::Uint(4, endianness, BitSize(16)), Some(4))));
+
+    assert_eq!(Template::parse_type("char[16]"), Ok((FieldType::string(16), None)));
+    assert_eq!(Template::parse_type("string8[2]"), Ok((FieldType::string(8), Some(2))));
+
+    assert!(Template::parse_type("uint16_be[0]").is_err());
+    assert!(Template::parse_type("uint16_be[]").is_err());
+    assert!(Template::parse_type("uint16_be[32").is_err());
+}
+
+#[test]
+fn test_parse_integer() {
+    assert_eq!(parse_integer("10"), Some(10));
+    assert_eq!(parse_integer(" 0xFF "), Some(255));
+    assert_eq!(parse_integer("0x"), None);
+    assert_eq!(parse_integer("ten"), None);
+}
+
+/// A directory of template files for a test, which is removed once the test
+/// is done, whether or not it passes.
+#[cfg(test)]
+struct TemplateDir(PathBuf);
+
+#[cfg(test)]
+impl TemplateDir {
+    fn new(name: &str) -> TemplateDir {
+        let dir = std::env::temp_dir().join(format!("inlay_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        TemplateDir(dir)
+    }
+
+    fn write(&self, file_name: &str, contents: &str) {
+        std::fs::write(self.0.join(file_name), contents).unwrap();
+    }
+
+    fn path(&self, file_name: &str) -> String {
+        self.0.join(file_name).display().to_string()
+    }
+
+    fn read(&self, file_name: &str) -> Result<Vec<Template>, TemplateError> {
+        Template::read_templates(&self.path(file_name))
+    }
+}
+
+#[cfg(test)]
+impl Drop for TemplateDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn test_read_templates_structure() {
+    let dir = TemplateDir::new("structure");
+
+    dir.write("header.csv", "type,description\nuint11_be,apid\nuint5_be,flags\n");
+    dir.write("packet.csv", "type,description\nstruct:header.csv,header\nuint8_be,length\n");
+    dir.write("cycle.csv", "type,description\nuint8_be,length\nstruct:cycle.csv,self\n");
+
+    let templates = dir.read("packet.csv").unwrap();
+    assert_eq!(templates.len(), 2);
+    assert_eq!(templates[0].num_bits(), 16);
+
+    let descriptions: Vec<String> =
+        record_columns(&templates).into_iter().map(|element| element.description).collect();
+    assert_eq!(descriptions, vec!("header.apid", "header.flags", "length"));
+
+    assert_eq!(dir.read("cycle.csv"),
+               Err(TemplateError::InFile("cycle.csv".to_string(),
+                                         Box::new(TemplateError::Cycle(dir.path("cycle.csv"))))));
+}
+
+#[test]
+fn test_read_templates_count_field() {
+    let dir = TemplateDir::new("count_field");
+
+    dir.write("entry.csv", "type,description\nuint8_be,id\n");
+    dir.write("packet.csv",
+              "type,description\nuint8_be,num entries\nstruct:entry.csv[num entries],entries\nuint16_be[ num entries ],samples\n");
+
+    let templates = dir.read("packet.csv").unwrap();
+    assert_eq!(templates[1].count_field, Some(FieldRef::new("num entries")));
+    assert_eq!(templates[1].type_name(), "struct:entry.csv[num entries]");
+    assert_eq!(templates[2].count_field, Some(FieldRef::new("num entries")));
+    assert_eq!(templates[2].type_name(), "uint16_be[num entries]");
+    assert!(templates[2].is_variable());
+}
+
+#[test]
+fn test_read_templates_blob() {
+    let dir = TemplateDir::new("blob");
+
+    dir.write("packet.csv", "type,description\nuint16_be,packetLen\nhex[packetLen + 1],data\n");
+    dir.write("no_length.csv", "type,description\nhex,data\n");
+    dir.write("fixed.csv", "type,description\nbase64[4],data\n");
+
+    let templates = dir.read("packet.csv").unwrap();
+    assert_eq!(templates[1].length_field, Some(FieldRef { field: "packetLen".to_string(), adjustment: 1 }));
+    assert_eq!(templates[1].count_field, None);
+    assert_eq!(templates[1].type_name(), "hex[packetLen+1]");
+
+    assert!(dir.read("no_length.csv").is_err());
+    assert!(dir.read("fixed.csv").is_err());
+
+    assert_eq!(FieldRef::parse("len - 2"), FieldRef { field: "len".to_string(), adjustment: -2 });
+    assert_eq!(FieldRef::parse("len - 2").to_string(), "len-2");
+    assert_eq!(FieldRef::parse("num entries"), FieldRef::new("num entries"));
+}
+
+#[test]
+fn test_read_templates_present_if() {
+    let dir = TemplateDir::new("present_if");
+
+    dir.write("header.csv", "type,description\nuint16_be,seconds\n");
+    dir.write("packet.csv",
+              "type,description,present_if\nuint8_be,secHeaderFlag,\nstruct:header.csv,secHeader,secHeaderFlag == 1\nuint8_be,apid,\n");
+    dir.write("invalid.csv", "type,description,present_if\nuint8_be,flag,flag ==\n");
+
+    let templates = dir.read("packet.csv").unwrap();
+    assert_eq!(templates[0].present_if, None);
+    assert_eq!(templates[1].present_if.as_ref().map(|condition| condition.to_string()),
+               Some("secHeaderFlag == 1".to_string()));
+    assert_eq!(templates[2].present_if, None);
+
+    assert_eq!(dir.read("invalid.csv"),
+               Err(TemplateError::LineNumber(2)));
+}
+
+#[test]
+fn test_read_templates_union() {
+    let dir = TemplateDir::new("union");
+
+    dir.write("hk.csv", "type,description\nuint16_be,temperature\n");
+    dir.write("mode.csv", "type,description,enum\nuint16_be,temperature,0=OFF\n");
+    dir.write("bodies.csv", "value,template\n1,hk.csv\n0x2,hk.csv\n*,\n");
+    dir.write("duplicate.csv", "value,template\n1,hk.csv\n1,hk.csv\n");
+    dir.write("converted.csv", "value,template\n1,hk.csv\n2,mode.csv\n");
+    dir.write("packet.csv", "type,description\nuint8_be,apid\nunion:bodies.csv[apid],body\n");
+    dir.write("no_field.csv", "type,description\nunion:bodies.csv,body\n");
+    dir.write("dup_packet.csv", "type,description\nuint8_be,apid\nunion:duplicate.csv[apid],body\n");
+    dir.write("conv_packet.csv", "type,description\nuint8_be,apid\nunion:converted.csv[apid],body\n");
+
+    let templates = dir.read("packet.csv").unwrap();
+    let union = templates[1].union.as_ref().unwrap();
+    assert_eq!(union.discriminator, FieldRef::new("apid"));
+    assert_eq!(union.branches.iter().map(|branch| branch.value).collect::<Vec<_>>(), vec!(Some(1), Some(2), None));
+    assert_eq!(union.branch(2).map(|branch| branch.structure.fields.len()), Some(1));
+    assert_eq!(union.branch(7).map(|branch| branch.structure.fields.len()), Some(0));
+    assert_eq!(union.condition(&union.branches[2]), "apid != 1 && apid != 2");
+    assert_eq!(templates[1].type_name(), "union:bodies.csv[apid]");
+
+    assert!(dir.read("no_field.csv").is_err());
+    assert!(dir.read("dup_packet.csv").is_err());
+    assert!(dir.read("conv_packet.csv").is_err());
+}
+
+#[test]
+fn test_read_templates_constant() {
+    let dir = TemplateDir::new("constant");
+
+    dir.write("packet.csv", "type,description,const\nuint32_be,sync,0x1ACFFC1D\nint8_be,offset,-2\nchar[4],magic,RIFF\nuint8_be,value,\n");
+    dir.write("too_big.csv", "type,description,const\nuint8_be,sync,0x100\n");
+    dir.write("too_long.csv", "type,description,const\nchar[2],magic,RIFF\n");
+    dir.write("float.csv", "type,description,const\nfloat_be,value,1.5\n");
+
+    let templates = dir.read("packet.csv").unwrap();
+    let constants: Vec<Option<String>> = templates.iter().map(|template| template.constant.clone()).collect();
+    assert_eq!(constants, vec!(Some("449838109".to_string()), Some("-2".to_string()), Some("RIFF".to_string()), None));
+    assert!(templates[0].is_hidden());
+    assert!(!templates[3].is_hidden());
+
+    assert!(dir.read("too_big.csv").is_err());
+    assert!(dir.read("too_long.csv").is_err());
+    assert!(dir.read("float.csv").is_err());
+}
+
+#[test]
+fn test_read_templates_checksum() {
+    let dir = TemplateDir::new("checksum");
+
+    dir.write("frame.csv", "type,description,span\nuint8_be[4],data,\ncrc16_ccitt_be,crc,\nsum8,sum,1..3\n");
+    dir.write("not_checksum.csv", "type,description,span\nuint8_be,data,0..1\n");
+    dir.write("bad_span.csv", "type,description,span\nsum8,sum,3..1\n");
+
+    let templates = dir.read("frame.csv").unwrap();
+    assert_eq!(templates[1].span, None);
+    assert_eq!(templates[2].span, Some(Span::Bytes(1, Some(3))));
+    assert_eq!(templates[2].type_name(), "sum8");
+
+    assert!(dir.read("not_checksum.csv").is_err());
+    assert!(dir.read("bad_span.csv").is_err());
+}
+
+#[test]
+fn test_read_templates_record_length() {
+    let dir = TemplateDir::new("record_length");
+
+    dir.write("packet.csv", "type,description\nuint16_be,packetLen\nrecord_length[packetLen+7],extra\n");
+    dir.write("not_last.csv", "type,description\nuint16_be,packetLen\nrecord_length[packetLen+7],\nuint8_be,after\n");
+    dir.write("no_field.csv", "type,description\nrecord_length,\n");
+    dir.write("nested.csv", "type,description\nstruct:packet.csv,packet\n");
+
+    let templates = dir.read("packet.csv").unwrap();
+    assert_eq!(templates[1].record_length, Some(FieldRef { field: "packetLen".to_string(), adjustment: 7 }));
+    assert_eq!(templates[1].type_name(), "record_length[packetLen+7]");
+    assert!(!templates[1].is_hidden());
+
+    assert!(dir.read("not_last.csv").is_err());
+    assert!(dir.read("no_field.csv").is_err());
+    assert!(dir.read("nested.csv").is_err());
+}
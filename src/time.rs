@@ -0,0 +1,504 @@
+use std::fmt;
+
+use regex::Regex;
+
+
+pub const PICOS_PER_SECOND: i128 = 1_000_000_000_000;
+
+const SECONDS_PER_DAY: i128 = 86_400;
+
+const PICOS_PER_DAY: i128 = SECONDS_PER_DAY * PICOS_PER_SECOND;
+
+/// The epoch of a time code, given in seconds since 1970-01-01T00:00:00Z.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub struct Epoch(pub i64);
+
+/// The CCSDS recommended epoch of 1958-01-01T00:00:00Z.
+pub const CCSDS_EPOCH: Epoch = Epoch(-378_691_200);
+
+impl Epoch {
+    /// Parse an epoch given as an ISO 8601 date, optionally with a time.
+    pub fn parse(text: &str) -> Option<Epoch> {
+        let picoseconds = parse_iso8601(text)?;
+
+        if picoseconds % PICOS_PER_SECOND != 0 {
+            error!("Epoch '{}' must be a whole number of seconds", text);
+            return None;
+        }
+
+        Some(Epoch((picoseconds / PICOS_PER_SECOND) as i64))
+    }
+
+    pub fn picoseconds(&self) -> i128 {
+        self.0 as i128 * PICOS_PER_SECOND
+    }
+}
+
+impl fmt::Display for Epoch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // epochs at midnight are written as just a date
+        let text = format_iso8601(self.picoseconds(), 0);
+        if self.0 as i128 % SECONDS_PER_DAY == 0 {
+            write!(f, "{}", &text[..text.find('T').unwrap()])
+        } else {
+            write!(f, "{}", text)
+        }
+    }
+}
+
+/// A point in time, with the number of decimal digits of a second
+/// it is written out with.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub struct Timestamp {
+    /// Picoseconds since 1970-01-01T00:00:00Z
+    pub picoseconds: i128,
+    pub digits: u32,
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format_iso8601(self.picoseconds, self.digits))
+    }
+}
+
+/// The unit and epoch of a count of time, such as milliseconds since 1970,
+/// used to read integer and float fields as timestamps.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub struct TimeScale {
+    /// The length of one count, in picoseconds
+    pub unit: i128,
+    pub epoch: Epoch,
+}
+
+impl TimeScale {
+    /// Parse a time scale given as a unit, one of 's', 'ms', 'us', or 'ns', optionally
+    /// followed by an epoch after an '@', such as 'ms@2000-01-01'. The default
+    /// epoch is 1970-01-01.
+    pub fn parse(text: &str) -> Option<TimeScale> {
+        let mut parts = text.trim().splitn(2, '@');
+
+        let unit = match parts.next()?.trim().to_lowercase().as_str() {
+            "s" => PICOS_PER_SECOND,
+            "ms" => 1_000_000_000,
+            "us" => 1_000_000,
+            "ns" => 1_000,
+
+            unit => {
+                error!("Time unit '{}' should be one of s, ms, us, or ns", unit);
+                return None;
+            },
+        };
+
+        let epoch = match parts.next() {
+            Some(epoch) => Epoch::parse(epoch)?,
+            None => Epoch(0),
+        };
+
+        Some(TimeScale { unit, epoch })
+    }
+
+    /// The number of decimal digits of a second in one count.
+    fn digits(&self) -> u32 {
+        12 - (self.unit as f64).log10().round() as u32
+    }
+
+    /// The time of a whole number of counts.
+    pub fn time(&self, counts: i128) -> Timestamp {
+        Timestamp { picoseconds: self.epoch.picoseconds() + counts * self.unit, digits: self.digits() }
+    }
+
+    /// The time of a fractional number of counts, given to nanoseconds.
+    pub fn time_from_float(&self, counts: f64) -> Timestamp {
+        let picoseconds = (counts * self.unit as f64).round() as i128;
+        Timestamp { picoseconds: self.epoch.picoseconds() + picoseconds, digits: self.digits().max(9) }
+    }
+
+    /// The number of counts for a time, rounded to the nearest count.
+    pub fn counts(&self, picoseconds: i128) -> i128 {
+        (picoseconds - self.epoch.picoseconds() + self.unit / 2).div_euclid(self.unit)
+    }
+
+    /// The fractional number of counts for a time.
+    pub fn counts_float(&self, picoseconds: i128) -> f64 {
+        (picoseconds - self.epoch.picoseconds()) as f64 / self.unit as f64
+    }
+}
+
+/// A CCSDS time code, as described in CCSDS 301.0-B.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub enum TimeCode {
+    /// Unsegmented time code, with the number of octets of coarse time (whole
+    /// seconds) and fine time (binary fractions of a second)
+    Cuc(u8, u8),
+
+    /// Day segmented time code, with the number of octets of days, followed by
+    /// milliseconds of the day, and the number of octets of submilliseconds
+    Cds(u8, u8),
+}
+
+impl TimeCode {
+    /// Create an unsegmented time code, checking its number of octets.
+    pub fn cuc(coarse: u8, fine: u8) -> Option<TimeCode> {
+        if (1..=4).contains(&coarse) && fine <= 3 {
+            Some(TimeCode::Cuc(coarse, fine))
+        } else {
+            error!("A CUC time code has 1 to 4 coarse octets and 0 to 3 fine octets, not {} and {}", coarse, fine);
+            None
+        }
+    }
+
+    /// Create a day segmented time code, checking its number of octets.
+    pub fn cds(days: u8, submillis: u8) -> Option<TimeCode> {
+        if (days == 2 || days == 3) && (submillis == 0 || submillis == 2 || submillis == 4) {
+            Some(TimeCode::Cds(days, submillis))
+        } else {
+            error!("A CDS time code has 2 or 3 day octets and 0, 2, or 4 submillisecond octets, not {} and {}", days, submillis);
+            None
+        }
+    }
+
+    pub fn num_bytes(&self) -> usize {
+        match self {
+            TimeCode::Cuc(coarse, fine) => (coarse + fine) as usize,
+            TimeCode::Cds(days, submillis) => (days + 4 + submillis) as usize,
+        }
+    }
+
+    /// The number of decimal digits of a second needed to show the resolution of the time code.
+    pub fn digits(&self) -> u32 {
+        match self {
+            TimeCode::Cuc(_, fine) => 3 * *fine as u32,
+            TimeCode::Cds(_, 0) => 3,
+            TimeCode::Cds(_, 2) => 6,
+            TimeCode::Cds(_, _) => 12,
+        }
+    }
+
+    /// The smallest step in time of the time code, in picoseconds.
+    fn resolution(&self) -> i128 {
+        match self {
+            TimeCode::Cuc(_, _) => 1,
+            TimeCode::Cds(_, 0) => 1_000_000_000,
+            TimeCode::Cds(_, 2) => 1_000_000,
+            TimeCode::Cds(_, _) => 1,
+        }
+    }
+
+    /// Decode a time from its bytes, which are always big endian.
+    pub fn decode(&self, epoch: Epoch, bytes: &[u8]) -> Option<Timestamp> {
+        let since_epoch = match self {
+            TimeCode::Cuc(_, fine) => {
+                let ticks = read_be(bytes);
+                let fine_bits = 8 * *fine as u32;
+
+                // round the binary fraction to the nearest picosecond
+                (ticks * PICOS_PER_SECOND + ((1 << fine_bits) >> 1)) >> fine_bits
+            },
+
+            TimeCode::Cds(days, submillis) => {
+                let days_bytes = *days as usize;
+                let day = read_be(&bytes[..days_bytes]);
+                let millis = read_be(&bytes[days_bytes..days_bytes + 4]);
+                let submilli = read_be(&bytes[days_bytes + 4..]);
+
+                // a day may have an extra second for a leap second
+                if millis >= (SECONDS_PER_DAY + 1) * 1000 {
+                    error!("CDS time has {} milliseconds of the day", millis);
+                    return None;
+                }
+
+                let submilli_picos = match submillis {
+                    0 => 0,
+                    2 if submilli < 1000 => submilli * 1_000_000,
+                    4 if submilli < 1_000_000_000 => submilli,
+                    _ => {
+                        error!("CDS time has {} submilliseconds, which is more than a millisecond", submilli);
+                        return None;
+                    },
+                };
+
+                day * PICOS_PER_DAY + millis * 1_000_000_000 + submilli_picos
+            },
+        };
+
+        Some(Timestamp { picoseconds: epoch.picoseconds() + since_epoch, digits: self.digits() })
+    }
+
+    /// Encode a time given in picoseconds since 1970-01-01T00:00:00Z, or None if
+    /// the time is before the epoch or too far after it to fit in the time code.
+    pub fn encode(&self, epoch: Epoch, picoseconds: i128) -> Option<Vec<u8>> {
+        let resolution = self.resolution();
+
+        // round to the nearest step of the time code
+        let since_epoch = (picoseconds - epoch.picoseconds() + resolution / 2).div_euclid(resolution) * resolution;
+
+        if since_epoch < 0 {
+            error!("Time {} is before the epoch {}", format_iso8601(picoseconds, 12), epoch);
+            return None;
+        }
+
+        let mut bytes = vec!();
+
+        match self {
+            TimeCode::Cuc(coarse, fine) => {
+                let fine_bits = 8 * *fine as u32;
+                let ticks = ((since_epoch << fine_bits) + PICOS_PER_SECOND / 2) / PICOS_PER_SECOND;
+
+                write_be(&mut bytes, ticks, (coarse + fine) as usize)?;
+            },
+
+            TimeCode::Cds(days, submillis) => {
+                let day = since_epoch / PICOS_PER_DAY;
+                let picos_of_day = since_epoch % PICOS_PER_DAY;
+
+                write_be(&mut bytes, day, *days as usize)?;
+                write_be(&mut bytes, picos_of_day / 1_000_000_000, 4)?;
+
+                match submillis {
+                    2 => write_be(&mut bytes, (picos_of_day % 1_000_000_000) / 1_000_000, 2)?,
+                    4 => write_be(&mut bytes, picos_of_day % 1_000_000_000, 4)?,
+                    _ => {},
+                }
+            },
+        }
+
+        Some(bytes)
+    }
+}
+
+impl fmt::Display for TimeCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeCode::Cuc(coarse, fine) => write!(f, "cuc{}.{}", coarse, fine),
+            TimeCode::Cds(days, 0) => write!(f, "cds{}", days),
+            TimeCode::Cds(days, submillis) => write!(f, "cds{}.{}", days, submillis),
+        }
+    }
+}
+
+fn read_be(bytes: &[u8]) -> i128 {
+    bytes.iter().fold(0, |value, byte| (value << 8) | *byte as i128)
+}
+
+fn write_be(bytes: &mut Vec<u8>, value: i128, num_bytes: usize) -> Option<()> {
+    if value >> (8 * num_bytes) != 0 {
+        error!("Time does not fit in its {} byte field", num_bytes);
+        return None;
+    }
+
+    for index in (0..num_bytes).rev() {
+        bytes.push((value >> (8 * index)) as u8);
+    }
+
+    Some(())
+}
+
+/// The number of days from 1970-01-01 to the given date in the proleptic Gregorian calendar.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The date in the proleptic Gregorian calendar that is the given number of days from 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Write out a time given in picoseconds since 1970-01-01T00:00:00Z as an
+/// ISO 8601 UTC time, with the given number of decimal digits of a second.
+pub fn format_iso8601(picoseconds: i128, digits: u32) -> String {
+    let digits = digits.min(12);
+    let unit = 10i128.pow(12 - digits);
+
+    // round to the number of digits shown, which may carry into the seconds
+    let picoseconds = (picoseconds + unit / 2).div_euclid(unit) * unit;
+
+    let seconds = picoseconds.div_euclid(PICOS_PER_SECOND);
+    let fraction = picoseconds.rem_euclid(PICOS_PER_SECOND) / unit;
+
+    let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY) as i64);
+    let second_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+
+    let mut text = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                           year, month, day,
+                           second_of_day / 3600, (second_of_day / 60) % 60, second_of_day % 60);
+
+    if digits > 0 {
+        text.push_str(&format!(".{:0width$}", fraction, width = digits as usize));
+    }
+
+    text.push('Z');
+
+    text
+}
+
+/// Parse an ISO 8601 UTC time, such as '2024-03-01T12:30:05.25Z', into picoseconds
+/// since 1970-01-01T00:00:00Z. The time may be left off, giving midnight, and a
+/// space may separate the date and time.
+pub fn parse_iso8601(text: &str) -> Option<i128> {
+    lazy_static! {
+      static ref ISO8601_REGEX: Regex =
+          Regex::new(r"(?i)^(-?\d{4,})-(\d{2})-(\d{2})(?:[t ](\d{2}):(\d{2})(?::(\d{2})(?:\.(\d+))?)?)?z?$").unwrap();
+    }
+
+    let text = text.trim();
+
+    let matches = match ISO8601_REGEX.captures(text) {
+        Some(matches) => matches,
+
+        None => {
+            error!("'{}' is not an ISO 8601 time", text);
+            return None;
+        },
+    };
+
+    let number = |index: usize| matches.get(index).map(|mat| mat.as_str().parse::<i64>().unwrap()).unwrap_or(0);
+
+    let year = matches[1].parse::<i64>().ok()?;
+    let month = number(2) as u32;
+    let day = number(3) as u32;
+    let (hour, minute, second) = (number(4), number(5), number(6));
+
+    // a second of 60 is allowed for leap seconds
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) ||
+       hour > 23 || minute > 59 || second > 60 {
+        error!("'{}' is not a valid time", text);
+        return None;
+    }
+
+    // fractions beyond picoseconds are ignored
+    let fraction = match matches.get(7) {
+        Some(digits) => {
+            let digits = &digits.as_str()[..digits.as_str().len().min(12)];
+            digits.parse::<i128>().unwrap() * 10i128.pow(12 - digits.len() as u32)
+        },
+
+        None => 0,
+    };
+
+    let seconds = days_from_civil(year, month, day) as i128 * SECONDS_PER_DAY +
+                  (hour * 3600 + minute * 60 + second) as i128;
+
+    Some(seconds * PICOS_PER_SECOND + fraction)
+}
+
+#[test]
+fn test_time_scale() {
+    let scale = TimeScale::parse("ms").unwrap();
+    assert_eq!(scale, TimeScale { unit: 1_000_000_000, epoch: Epoch(0) });
+    assert_eq!(scale.time(1500).to_string(), "1970-01-01T00:00:01.500Z");
+    assert_eq!(scale.counts(parse_iso8601("1970-01-01T00:00:01.5").unwrap()), 1500);
+
+    let scale = TimeScale::parse("s@2000-01-01").unwrap();
+    assert_eq!(scale.time(-1).to_string(), "1999-12-31T23:59:59Z");
+    assert_eq!(scale.time_from_float(0.25).to_string(), "2000-01-01T00:00:00.250000000Z");
+    assert_eq!(scale.counts_float(parse_iso8601("2000-01-01T00:00:00.25").unwrap()), 0.25);
+
+    assert_eq!(TimeScale::parse("ns").unwrap().time(1).to_string(), "1970-01-01T00:00:00.000000001Z");
+    assert!(TimeScale::parse("minutes").is_none());
+    assert!(TimeScale::parse("s@noon").is_none());
+}
+
+#[test]
+fn test_civil_days() {
+    assert_eq!(days_from_civil(1970, 1, 1), 0);
+    assert_eq!(days_from_civil(1958, 1, 1), -4383);
+    assert_eq!(days_from_civil(2000, 3, 1), 11017);
+    assert_eq!(civil_from_days(-4383), (1958, 1, 1));
+    assert_eq!(civil_from_days(11016), (2000, 2, 29));
+
+    for days in -800_000..800_000 {
+        let (year, month, day) = civil_from_days(days);
+        assert_eq!(days_from_civil(year, month, day), days);
+    }
+}
+
+#[test]
+fn test_iso8601() {
+    assert_eq!(format_iso8601(0, 0), "1970-01-01T00:00:00Z");
+    assert_eq!(format_iso8601(-PICOS_PER_SECOND / 2, 3), "1969-12-31T23:59:59.500Z");
+    assert_eq!(format_iso8601(PICOS_PER_SECOND - 1, 3), "1970-01-01T00:00:01.000Z");
+
+    assert_eq!(parse_iso8601("1970-01-01"), Some(0));
+    assert_eq!(parse_iso8601("1970-01-01T00:00:01.5Z"), Some(3 * PICOS_PER_SECOND / 2));
+    assert_eq!(parse_iso8601("1969-12-31 23:59:59"), Some(-PICOS_PER_SECOND));
+    assert_eq!(parse_iso8601("2024-02-29t12:00z"), Some((days_from_civil(2024, 2, 29) as i128 * 86400 + 43200) * PICOS_PER_SECOND));
+
+    assert_eq!(parse_iso8601("2023-02-29"), None);
+    assert_eq!(parse_iso8601("2024-01-01T24:00:00"), None);
+    assert_eq!(parse_iso8601("yesterday"), None);
+
+    let time = parse_iso8601("2024-03-01T12:30:05.123456Z").unwrap();
+    assert_eq!(format_iso8601(time, 6), "2024-03-01T12:30:05.123456Z");
+
+    assert_eq!(Epoch::parse("1958-01-01"), Some(CCSDS_EPOCH));
+    assert_eq!(CCSDS_EPOCH.to_string(), "1958-01-01");
+    assert_eq!(Epoch(1).to_string(), "1970-01-01T00:00:01Z");
+}
+
+#[test]
+fn test_time_code_cuc() {
+    let code = TimeCode::cuc(4, 2).unwrap();
+    let epoch = CCSDS_EPOCH;
+
+    // one and a half seconds after the epoch
+    let time = code.decode(epoch, &[0, 0, 0, 1, 0x80, 0]).unwrap();
+    assert_eq!(time.to_string(), "1958-01-01T00:00:01.500000Z");
+    assert_eq!(code.encode(epoch, time.picoseconds), Some(vec!(0, 0, 0, 1, 0x80, 0)));
+
+    let code = TimeCode::cuc(4, 0).unwrap();
+    let time = parse_iso8601("2000-01-01T11:58:55.816Z").unwrap();
+    let bytes = code.encode(Epoch(0), time).unwrap();
+    assert_eq!(code.decode(Epoch(0), &bytes).unwrap().to_string(), "2000-01-01T11:58:56Z");
+
+    assert_eq!(code.encode(Epoch(0), -PICOS_PER_SECOND), None);
+    assert_eq!(TimeCode::cuc(1, 0).unwrap().encode(Epoch(0), 256 * PICOS_PER_SECOND), None);
+
+    assert!(TimeCode::cuc(0, 2).is_none());
+    assert!(TimeCode::cuc(4, 4).is_none());
+}
+
+#[test]
+fn test_time_code_cds() {
+    let code = TimeCode::cds(2, 2).unwrap();
+    let epoch = CCSDS_EPOCH;
+
+    let bytes = [0x00, 0x02, 0x00, 0x00, 0x03, 0xE9, 0x00, 0x07];
+    let time = code.decode(epoch, &bytes).unwrap();
+    assert_eq!(time.to_string(), "1958-01-03T00:00:01.001007Z");
+    assert_eq!(code.encode(epoch, time.picoseconds), Some(bytes.to_vec()));
+
+    let code = TimeCode::cds(2, 0).unwrap();
+    assert_eq!(code.decode(epoch, &[0, 0, 0, 0, 0, 1]).unwrap().to_string(), "1958-01-01T00:00:00.001Z");
+
+    // submilliseconds must be less than a millisecond
+    assert!(TimeCode::cds(2, 2).unwrap().decode(epoch, &[0, 0, 0, 0, 0, 0, 0x03, 0xE8]).is_none());
+
+    assert!(TimeCode::cds(1, 0).is_none());
+    assert!(TimeCode::cds(2, 1).is_none());
+}
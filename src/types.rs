@@ -1,10 +1,17 @@
 use std::fmt;
 use std::str::FromStr;
+use std::convert::TryFrom;
 use std::vec::*;
 use std::iter::*;
 
 use regex::Regex;
 
+pub use crate::varint::VarintEncoding;
+pub use crate::bcd::BcdPacking;
+pub use crate::time::{TimeCode, TimeScale, Epoch, Timestamp, CCSDS_EPOCH};
+pub use crate::blob::BlobFormat;
+pub use crate::checksum::ChecksumKind;
+
 
 /// Rename usize for clarity when dealing with a number of bits.
 pub type NumBits = usize;
@@ -32,7 +39,7 @@ pub enum Signedness {
 
 /// A field type describes a primitive binary object- either
 /// a signed integer, an unsigned integer, or a floating point
-/// number (f32 or f64).
+/// number (half, bfloat16, f32 or f64).
 ///
 /// A field type also carries information about the memory
 /// area that contains it. This is used for bitfields to indicate
@@ -50,6 +57,40 @@ pub enum FieldType {
 
     /// Double Precision Float
     Double(Endianness),
+
+    /// Half Precision Float (IEEE 754 binary16)
+    Half(Endianness),
+
+    /// Brain Float (bfloat16), the upper half of a single precision float
+    BFloat16(Endianness),
+
+    /// Boolean flag, which must be either 0 or 1
+    Bool(NumBits, Endianness, BitSize),
+
+    /// Binary-coded decimal, with each decimal digit in a nibble or byte
+    Bcd(NumBits, Endianness, BitSize, BcdPacking),
+
+    /// Fixed width character string, given in bytes
+    Str(NumBytes, Padding, TextEncoding),
+
+    /// Padding or reserved bits, which are not decoded
+    Pad(NumBits, Endianness, BitSize),
+
+    /// Variable length integer, whose size is only known when it is read
+    Varint(VarintEncoding),
+
+    /// CCSDS time code, relative to an epoch
+    Time(TimeCode, Epoch),
+
+    /// Variable length bytes, whose length is given by an earlier field
+    Blob(BlobFormat),
+
+    /// Checksum or CRC over other bytes of the record
+    Checksum(ChecksumKind, Endianness),
+
+    /// Structure of fields given by another template, which is expanded
+    /// into its fields before decoding or encoding
+    Struct,
 }
 
 impl HasNumBits for FieldType {
@@ -59,41 +100,60 @@ impl HasNumBits for FieldType {
             FieldType::Uint(num_bits, _, _) => *num_bits,
             FieldType::Float(_) => 32,
             FieldType::Double(_) => 64,
+            FieldType::Half(_) => 16,
+            FieldType::BFloat16(_) => 16,
+            FieldType::Bool(num_bits, _, _) => *num_bits,
+            FieldType::Bcd(num_bits, _, _, _) => *num_bits,
+            FieldType::Str(num_bytes, _, _) => num_bytes * 8,
+            FieldType::Pad(num_bits, _, _) => *num_bits,
+            FieldType::Varint(_) => 0,
+            FieldType::Time(code, _) => code.num_bytes() * 8,
+            FieldType::Blob(_) => 0,
+            FieldType::Checksum(kind, _) => kind.num_bits(),
+            FieldType::Struct => 0,
         }
     }
 }
 
 impl FieldType {
     pub fn u8(endianness: Endianness) -> FieldType {
-        FieldType::Uint(8, endianness, BitSize::Bits8)
+        FieldType::Uint(8, endianness, BitSize(8))
     }
 
     pub fn u16(endianness: Endianness) -> FieldType {
-        FieldType::Uint(16, endianness, BitSize::Bits16)
+        FieldType::Uint(16, endianness, BitSize(16))
     }
 
     pub fn u32(endianness: Endianness) -> FieldType {
-        FieldType::Uint(32, endianness, BitSize::Bits32)
+        FieldType::Uint(32, endianness, BitSize(32))
     }
 
     pub fn u64(endianness: Endianness) -> FieldType {
-        FieldType::Uint(64, endianness, BitSize::Bits64)
+        FieldType::Uint(64, endianness, BitSize(64))
+    }
+
+    pub fn u128(endianness: Endianness) -> FieldType {
+        FieldType::Uint(128, endianness, BitSize(128))
     }
 
     pub fn i8(endianness: Endianness) -> FieldType {
-        FieldType::Int(8, endianness, BitSize::Bits8)
+        FieldType::Int(8, endianness, BitSize(8))
     }
 
     pub fn i16(endianness: Endianness) -> FieldType {
-        FieldType::Int(16, endianness, BitSize::Bits16)
+        FieldType::Int(16, endianness, BitSize(16))
     }
 
     pub fn i32(endianness: Endianness) -> FieldType {
-        FieldType::Int(32, endianness, BitSize::Bits32)
+        FieldType::Int(32, endianness, BitSize(32))
     }
 
     pub fn i64(endianness: Endianness) -> FieldType {
-        FieldType::Int(64, endianness, BitSize::Bits64)
+        FieldType::Int(64, endianness, BitSize(64))
+    }
+
+    pub fn i128(endianness: Endianness) -> FieldType {
+        FieldType::Int(128, endianness, BitSize(128))
     }
 
     pub fn float(endianness: Endianness) -> FieldType {
@@ -103,6 +163,22 @@ impl FieldType {
     pub fn double(endianness: Endianness) -> FieldType {
         FieldType::Double(endianness)
     }
+
+    pub fn half(endianness: Endianness) -> FieldType {
+        FieldType::Half(endianness)
+    }
+
+    pub fn bfloat16(endianness: Endianness) -> FieldType {
+        FieldType::BFloat16(endianness)
+    }
+
+    pub fn bool(endianness: Endianness) -> FieldType {
+        FieldType::Bool(8, endianness, BitSize(8))
+    }
+
+    pub fn string(num_bytes: NumBytes) -> FieldType {
+        FieldType::Str(num_bytes, Padding::Nul, TextEncoding::Ascii)
+    }
 }
 
 impl fmt::Display for FieldType {
@@ -134,6 +210,83 @@ impl fmt::Display for FieldType {
             FieldType::Double(endianness) => {
                 write!(f, "double_{}", endianness.to_string())
             },
+
+            FieldType::Half(endianness) => {
+                write!(f, "half_{}", endianness.to_string())
+            },
+
+            FieldType::BFloat16(endianness) => {
+                write!(f, "bfloat16_{}", endianness.to_string())
+            },
+
+            FieldType::Bool(num_bits, endianness, bit_size) => {
+                if *num_bits != bit_size.num_bits() {
+                    write!(f, "bool{}_{}:{}", num_bits, endianness.to_string(), bit_size.num_bits())
+                } else {
+                    write!(f, "bool{}_{}", num_bits, endianness.to_string())
+                }
+            },
+
+            FieldType::Bcd(num_bits, endianness, bit_size, packing) => {
+                if *num_bits != bit_size.num_bits() {
+                    write!(f, "{}{}_{}:{}", packing, num_bits, endianness.to_string(), bit_size.num_bits())
+                } else {
+                    write!(f, "{}{}_{}", packing, num_bits, endianness.to_string())
+                }
+            },
+
+            FieldType::Str(num_bytes, padding, encoding) => {
+                write!(f, "string{}", num_bytes)?;
+
+                // only write out options that differ from the defaults
+                if *padding != Padding::Nul {
+                    write!(f, "_{}", padding)?;
+                }
+
+                if *encoding != TextEncoding::Ascii {
+                    write!(f, "_{}", encoding)?;
+                }
+
+                Ok(())
+            },
+
+            FieldType::Pad(num_bits, endianness, bit_size) => {
+                if *num_bits != bit_size.num_bits() {
+                    write!(f, "pad{}_{}:{}", num_bits, endianness.to_string(), bit_size.num_bits())
+                } else {
+                    write!(f, "pad{}_{}", num_bits, endianness.to_string())
+                }
+            },
+
+            FieldType::Varint(encoding) => {
+                write!(f, "{}", encoding)
+            },
+
+            FieldType::Time(code, epoch) => {
+                // only write out the epoch if it is not the CCSDS epoch
+                if *epoch != CCSDS_EPOCH {
+                    write!(f, "{}@{}", code, epoch)
+                } else {
+                    write!(f, "{}", code)
+                }
+            },
+
+            FieldType::Blob(format) => {
+                write!(f, "{}", format)
+            },
+
+            // single byte checksums have no endianness
+            FieldType::Checksum(kind, endianness) => {
+                if kind.num_bits() == 8 {
+                    write!(f, "{}", kind)
+                } else {
+                    write!(f, "{}_{}", kind, endianness.to_string())
+                }
+            },
+
+            FieldType::Struct => {
+                write!(f, "struct")
+            },
         }
     }
 }
@@ -149,6 +302,32 @@ impl FieldType {
             FieldType::Float(endianness) => *endianness,
 
             FieldType::Double(endianness) => *endianness,
+
+            FieldType::Half(endianness) => *endianness,
+
+            FieldType::BFloat16(endianness) => *endianness,
+
+            FieldType::Bool(_, endianness, _) => *endianness,
+
+            FieldType::Bcd(_, endianness, _, _) => *endianness,
+
+            // strings are read byte by byte, so they have no endianness
+            FieldType::Str(_, _, _) => Endianness::Big,
+
+            FieldType::Pad(_, endianness, _) => *endianness,
+
+            // varints are read byte by byte, least significant first
+            FieldType::Varint(_) => Endianness::Little,
+
+            // time codes are always big endian
+            FieldType::Time(_, _) => Endianness::Big,
+
+            // blobs are read byte by byte, so they have no endianness
+            FieldType::Blob(_) => Endianness::Big,
+
+            FieldType::Checksum(_, endianness) => *endianness,
+
+            FieldType::Struct => Endianness::Big,
         }
     }
 
@@ -161,6 +340,31 @@ impl FieldType {
             FieldType::Float(_) => 32,
 
             FieldType::Double(_) => 64,
+
+            FieldType::Half(_) => 16,
+
+            FieldType::BFloat16(_) => 16,
+
+            FieldType::Bool(num_bits, _, _) => *num_bits,
+
+            FieldType::Bcd(num_bits, _, _, _) => *num_bits,
+
+            FieldType::Str(num_bytes, _, _) => num_bytes * 8,
+
+            FieldType::Pad(num_bits, _, _) => *num_bits,
+
+            // varints have no fixed size
+            FieldType::Varint(_) => 0,
+
+            FieldType::Time(code, _) => code.num_bytes() * 8,
+
+            // blobs have no fixed size
+            FieldType::Blob(_) => 0,
+
+            FieldType::Checksum(kind, _) => kind.num_bits(),
+
+            // the size of a structure is the size of its fields
+            FieldType::Struct => 0,
         }
     }
 
@@ -170,16 +374,53 @@ impl FieldType {
 
             FieldType::Uint(_, _, bit_size) => *bit_size,
 
-            FieldType::Float(_) => BitSize::Bits32,
+            FieldType::Float(_) => BitSize(32),
+
+            FieldType::Double(_) => BitSize(64),
+
+            FieldType::Half(_) => BitSize(16),
+
+            FieldType::BFloat16(_) => BitSize(16),
 
-            FieldType::Double(_) => BitSize::Bits64,
+            FieldType::Bool(_, _, bit_size) => *bit_size,
+
+            FieldType::Bcd(_, _, bit_size, _) => *bit_size,
+
+            FieldType::Str(_, _, _) => BitSize(8),
+
+            FieldType::Pad(_, _, bit_size) => *bit_size,
+
+            FieldType::Varint(_) => BitSize(8),
+
+            FieldType::Time(_, _) => BitSize(8),
+
+            FieldType::Blob(_) => BitSize(8),
+
+            FieldType::Checksum(kind, _) => BitSize(kind.num_bits()),
+
+            FieldType::Struct => BitSize(8),
         }
     }
 
+    /// Whether this type is padding, which is skipped when decoding
+    /// and filled in when encoding.
+    pub fn is_pad(&self) -> bool {
+        matches!(self, FieldType::Pad(_, _, _))
+    }
+
+    pub fn is_checksum(&self) -> bool {
+        matches!(self, FieldType::Checksum(_, _))
+    }
+
+    /// Whether this type has a size that is only known when it is read.
+    pub fn is_variable(&self) -> bool {
+        matches!(self, FieldType::Varint(_) | FieldType::Blob(_))
+    }
+
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct FieldParseError(());
+pub struct FieldParseError(pub(crate) ());
 
 impl FromStr for FieldType {
     type Err = FieldParseError;
@@ -187,20 +428,145 @@ impl FromStr for FieldType {
     fn from_str(type_str: &str) -> Result<FieldType, FieldParseError> {
         lazy_static! {
           static ref TYPE_REGEX: Regex =
-              Regex::new(r"(float|double|int|uint)(\d{0,2})_(be|le)(:8|:16|:32|:64)?").unwrap();
+              Regex::new(r"^(float|double|half|bfloat16|int|uint|bool|flag|bcd|ubcd)(\d*)_(be|le)(:\d+)?$").unwrap();
+
+          static ref STRING_REGEX: Regex =
+              Regex::new(r"^(char|string)(\d*)(_nul|_space)?(_ascii|_latin1|_utf8)?$").unwrap();
+
+          static ref TIME_REGEX: Regex =
+              Regex::new(r"^(?:cuc(\d)\.(\d)|cds(\d)(?:\.(\d))?)(?:@(.+))?$").unwrap();
+
+          static ref PAD_REGEX: Regex =
+              Regex::new(r"^(pad|reserved)(\d*)(_be|_le)?(:\d+)?$").unwrap();
+
+          static ref BLOB_REGEX: Regex =
+              Regex::new(r"^(?:hex|base64|text(_ascii|_latin1|_utf8)?)$").unwrap();
+
+          static ref CHECKSUM_REGEX: Regex =
+              Regex::new(r"^(crc16_ccitt|crc32|fletcher16|sum8|sum16|xor8)(_be|_le)?$").unwrap();
+        }
+
+        let type_str = type_str.trim().to_lowercase();
+
+        // a 'varint' is an unsigned LEB128, as used in protobuf
+        match type_str.as_str() {
+            "uleb128" | "varint" => return Ok(FieldType::Varint(VarintEncoding::ULeb128)),
+            "sleb128" => return Ok(FieldType::Varint(VarintEncoding::SLeb128)),
+            "zigzag" => return Ok(FieldType::Varint(VarintEncoding::ZigZag)),
+            _ => {},
+        }
+
+        // blobs are given as hex, base64, or text in a character encoding
+        if let Some(matches) = BLOB_REGEX.captures(&type_str) {
+            let format = match (type_str.as_str(), matches.get(1).map(|mat| mat.as_str())) {
+                ("hex", _) => BlobFormat::Hex,
+                ("base64", _) => BlobFormat::Base64,
+                (_, Some("_latin1")) => BlobFormat::Text(TextEncoding::Latin1),
+                (_, Some("_utf8")) => BlobFormat::Text(TextEncoding::Utf8),
+                (_, _) => BlobFormat::Text(TextEncoding::Ascii),
+            };
+
+            return Ok(FieldType::Blob(format));
+        }
+
+        // checksums default to big endian, and single byte checksums have no endianness
+        if let Some(matches) = CHECKSUM_REGEX.captures(&type_str) {
+            let kind = match &matches[1] {
+                "crc16_ccitt" => ChecksumKind::Crc16Ccitt,
+                "crc32" => ChecksumKind::Crc32,
+                "fletcher16" => ChecksumKind::Fletcher16,
+                "sum8" => ChecksumKind::Sum8,
+                "sum16" => ChecksumKind::Sum16,
+                _ => ChecksumKind::Xor8,
+            };
+
+            let endianness = match matches.get(2).map(|mat| mat.as_str()) {
+                Some("_le") => Endianness::Little,
+                _ => Endianness::Big,
+            };
+
+            return Ok(FieldType::Checksum(kind, endianness));
+        }
+
+        if let Some(matches) = STRING_REGEX.captures(&type_str) {
+            // a 'char' is a single byte, while a 'string' must give its size in bytes
+            let num_bytes = match (&matches[1], &matches[2]) {
+                ("char", "") => 1,
+                (_, digits) => digits.parse::<NumBytes>().or(Err(FieldParseError(())))?,
+            };
+
+            if num_bytes == 0 {
+                error!("String type '{}' must have at least one byte", type_str);
+                return Err(FieldParseError(()));
+            }
+
+            let padding = match matches.get(3).map(|mat| mat.as_str()) {
+                Some("_space") => Padding::Space,
+                _ => Padding::Nul,
+            };
+
+            let encoding = match matches.get(4).map(|mat| mat.as_str()) {
+                Some("_latin1") => TextEncoding::Latin1,
+                Some("_utf8") => TextEncoding::Utf8,
+                _ => TextEncoding::Ascii,
+            };
+
+            return Ok(FieldType::Str(num_bytes, padding, encoding));
         }
 
-        let type_str = type_str.to_lowercase();
+        if let Some(matches) = TIME_REGEX.captures(&type_str) {
+            let octets = |index: usize| matches.get(index).map(|mat| mat.as_str().parse::<u8>().unwrap()).unwrap_or(0);
+
+            // time codes use the CCSDS epoch unless given another after an '@'
+            let epoch = match matches.get(5) {
+                Some(epoch) => Epoch::parse(epoch.as_str()).ok_or(FieldParseError(()))?,
+                None => CCSDS_EPOCH,
+            };
+
+            let code = if matches.get(1).is_some() {
+                TimeCode::cuc(octets(1), octets(2))
+            } else {
+                TimeCode::cds(octets(3), octets(4))
+            };
+
+            return Ok(FieldType::Time(code.ok_or(FieldParseError(()))?, epoch));
+        }
+
+        if let Some(matches) = PAD_REGEX.captures(&type_str) {
+            // padding defaults to a single byte, so 'pad[4]' is four bytes of padding
+            let num_bits = match &matches[2] {
+                "" => 8,
+                digits => digits.parse::<NumBits>().or(Err(FieldParseError(())))?,
+            };
+
+            let endianness = match matches.get(3).map(|mat| mat.as_str()) {
+                Some("_le") => Endianness::Little,
+                _ => Endianness::Big,
+            };
+
+            let within_bits = BitSize::for_field(num_bits, matches.get(4).map(|mat| &mat.as_str()[1..]))?;
+
+            return Ok(FieldType::Pad(num_bits, endianness, within_bits));
+        }
 
         let matches = TYPE_REGEX.captures(&type_str).ok_or(FieldParseError(()))?;
 
+        // only integers, booleans, and decimals are given a number of bits, although floats may give their usual size
+        match (&matches[1], &matches[2]) {
+            ("int", _) | ("uint", _) | ("bool", _) | ("flag", _) | ("bcd", _) | ("ubcd", _) |
+            (_, "") | ("float", "32") | ("double", "64") => {},
+
+            _ => {
+                error!("Type '{}' does not take a number of bits", type_str);
+                return Err(FieldParseError(()));
+            },
+        }
+
         match &matches[1] {
             "uint" => {
                 let num_bits = matches[2].parse::<NumBits>().or(Err(FieldParseError(())))?;
 
-                let within_bits =
-                    matches.get(4).map(|mat| BitSize::from_str_bits(&mat.as_str()[1..]))
-                                  .unwrap_or(BitSize::fits_within(num_bits));
+                let within_bits = BitSize::for_field(num_bits, matches.get(4).map(|mat| &mat.as_str()[1..]))?;
 
                 match &matches[3] {
                     "be" => Ok(FieldType::Uint(num_bits, Endianness::Big, within_bits)),
@@ -217,9 +583,7 @@ impl FromStr for FieldType {
             "int" => {
                 let num_bits = matches[2].parse::<NumBits>().or(Err(FieldParseError(())))?;
 
-                let within_bits =
-                    matches.get(4).map(|mat| BitSize::from_str_bits(&mat.as_str()[1..]))
-                                 .unwrap_or(BitSize::fits_within(num_bits));
+                let within_bits = BitSize::for_field(num_bits, matches.get(4).map(|mat| &mat.as_str()[1..]))?;
 
                 match &matches[3] {
                     "be" => Ok(FieldType::Int(num_bits, Endianness::Big, within_bits)),
@@ -233,6 +597,55 @@ impl FromStr for FieldType {
                 }
             },
 
+            "bool" | "flag" => {
+                // a boolean defaults to a single byte
+                let num_bits = match &matches[2] {
+                    "" => 8,
+                    digits => digits.parse::<NumBits>().or(Err(FieldParseError(())))?,
+                };
+
+                let within_bits = BitSize::for_field(num_bits, matches.get(4).map(|mat| &mat.as_str()[1..]))?;
+
+                match &matches[3] {
+                    "be" => Ok(FieldType::Bool(num_bits, Endianness::Big, within_bits)),
+
+                    "le" => Ok(FieldType::Bool(num_bits, Endianness::Little, within_bits)),
+
+                     _ => {
+                         error!("Endianness '{}' not expected!", &matches[3]);
+                         Err(FieldParseError(()))
+                     },
+                }
+            },
+
+            "bcd" | "ubcd" => {
+                let packing = if &matches[1] == "bcd" { BcdPacking::Packed } else { BcdPacking::Unpacked };
+
+                // a decimal defaults to a single byte
+                let num_bits = match &matches[2] {
+                    "" => 8,
+                    digits => digits.parse::<NumBits>().or(Err(FieldParseError(())))?,
+                };
+
+                if num_bits % packing.digit_bits() != 0 {
+                    error!("Decimal type '{}' must be a whole number of {} bit digits", type_str, packing.digit_bits());
+                    return Err(FieldParseError(()));
+                }
+
+                let within_bits = BitSize::for_field(num_bits, matches.get(4).map(|mat| &mat.as_str()[1..]))?;
+
+                match &matches[3] {
+                    "be" => Ok(FieldType::Bcd(num_bits, Endianness::Big, within_bits, packing)),
+
+                    "le" => Ok(FieldType::Bcd(num_bits, Endianness::Little, within_bits, packing)),
+
+                     _ => {
+                         error!("Endianness '{}' not expected!", &matches[3]);
+                         Err(FieldParseError(()))
+                     },
+                }
+            },
+
             "float" => {
                 match &matches[3] {
                     "be" => Ok(FieldType::Float(Endianness::Big)),
@@ -259,6 +672,32 @@ impl FromStr for FieldType {
                 }
             },
 
+            "half" => {
+                match &matches[3] {
+                    "be" => Ok(FieldType::Half(Endianness::Big)),
+
+                    "le" => Ok(FieldType::Half(Endianness::Little)),
+
+                     _ => {
+                         error!("Endianness '{}' not expected!", &matches[3]);
+                         Err(FieldParseError(()))
+                     },
+                }
+            },
+
+            "bfloat16" => {
+                match &matches[3] {
+                    "be" => Ok(FieldType::BFloat16(Endianness::Big)),
+
+                    "le" => Ok(FieldType::BFloat16(Endianness::Little)),
+
+                     _ => {
+                         error!("Endianness '{}' not expected!", &matches[3]);
+                         Err(FieldParseError(()))
+                     },
+                }
+            },
+
             _ => {
                 error!("Type '{}' unexpected in field type '{}'", &matches[1], type_str);
                 Err(FieldParseError(()))
@@ -271,59 +710,159 @@ impl FromStr for FieldType {
 /// A BitSize is a number of bits for a particular field.
 /// This is used when processing bit fields, which are nested
 /// inside of a larger structure of a fixed number of bits.
+///
+/// A BitSize is always a whole number of bytes, up to MAX_BIT_SIZE bits.
 #[derive(PartialEq, Eq, Debug, Copy, Clone, Deserialize)]
-pub enum BitSize {
-    Bits8,
-    Bits16,
-    Bits32,
-    Bits64,
-}
+pub struct BitSize(pub NumBits);
+
+/// The largest bit field supported.
+pub const MAX_BIT_SIZE: NumBits = 128;
 
 impl BitSize {
-    pub fn from_str_bits(chars: &str) -> Self {
-        match chars {
-            "8"  => BitSize::Bits8,
-            "16" => BitSize::Bits16,
-            "32" => BitSize::Bits32,
-            "64" => BitSize::Bits64,
-            _    => panic!("{} chars not supports for BitSize"),
+    pub fn from_str_bits(chars: &str) -> Result<Self, FieldParseError> {
+        let num_bits = chars.parse::<NumBits>().or(Err(FieldParseError(())))?;
+
+        if num_bits == 0 || !num_bits.is_multiple_of(8) || num_bits > MAX_BIT_SIZE {
+            error!("Bit field size {} must be a whole number of bytes, up to {} bits", chars, MAX_BIT_SIZE);
+            Err(FieldParseError(()))
+        } else {
+            Ok(BitSize(num_bits))
         }
     }
 
     pub fn num_bytes(&self) -> NumBytes {
-        match self {
-            BitSize::Bits8  => 1,
-            BitSize::Bits16 => 2,
-            BitSize::Bits32 => 4,
-            BitSize::Bits64 => 8,
-        }
+        self.0 / 8
     }
 
+    /// The smallest BitSize that can hold the given number of bits.
     pub fn fits_within(num_bits: NumBits) -> Self {
-        if num_bits <= 8 {
-            BitSize::Bits8
-        } else if num_bits <= 16 {
-            BitSize::Bits16
-        } else if num_bits <= 32 {
-            BitSize::Bits32
-        } else if num_bits <= 64 {
-            BitSize::Bits64
-        } else {
-            panic!("Bit sizes of {} not supported", num_bits);
+        BitSize(num_bits.div_ceil(8) * 8)
+    }
+
+    /// The BitSize holding a field of the given number of bits. This is either given
+    /// explicitly, such as the '16' in 'uint3_be:16', or is the smallest that fits the field.
+    pub fn for_field(num_bits: NumBits, within: Option<&str>) -> Result<Self, FieldParseError> {
+        if num_bits == 0 || num_bits > MAX_BIT_SIZE {
+            error!("Field size of {} bits must be between 1 and {} bits", num_bits, MAX_BIT_SIZE);
+            return Err(FieldParseError(()));
+        }
+
+        let bit_size = match within {
+            Some(chars) => BitSize::from_str_bits(chars)?,
+            None => BitSize::fits_within(num_bits),
+        };
+
+        if bit_size.num_bits() < num_bits {
+            error!("Field of {} bits does not fit within {} bits", num_bits, bit_size.num_bits());
+            return Err(FieldParseError(()));
         }
+
+        Ok(bit_size)
     }
 }
 
 impl HasNumBits for BitSize {
     fn num_bits(&self) -> NumBits {
-        self.num_bytes() * 8
+        self.0
+    }
+}
+
+/// Padding is the byte used to fill out the unused part of a
+/// fixed width string.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub enum Padding {
+    Nul,
+    Space,
+}
+
+impl Padding {
+    pub fn byte(&self) -> u8 {
+        match self {
+            Padding::Nul => 0,
+            Padding::Space => b' ',
+        }
+    }
+
+    /// Remove padding from the end of a string's bytes. NUL padded
+    /// strings end at their first NUL.
+    pub fn trim<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        match self {
+            Padding::Nul => {
+                let len = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+                &bytes[..len]
+            },
+
+            Padding::Space => {
+                let len = bytes.iter().rposition(|byte| *byte != b' ').map_or(0, |index| index + 1);
+                &bytes[..len]
+            },
+        }
+    }
+}
+
+impl fmt::Display for Padding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Padding::Nul => write!(f, "nul"),
+            Padding::Space => write!(f, "space"),
+        }
+    }
+}
+
+/// The character encoding used for the bytes of a string.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub enum TextEncoding {
+    Ascii,
+    Latin1,
+    Utf8,
+}
+
+impl TextEncoding {
+    /// Decode bytes into text. Bytes that are not valid in the
+    /// encoding are replaced with the unicode replacement character.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            TextEncoding::Ascii => {
+                bytes.iter().map(|byte| if byte.is_ascii() { *byte as char } else { '\u{FFFD}' }).collect()
+            },
+
+            TextEncoding::Latin1 => bytes.iter().map(|byte| *byte as char).collect(),
+
+            TextEncoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+        }
+    }
+
+    /// Encode text into bytes, or None if the text contains a character
+    /// that can not be represented in the encoding.
+    pub fn encode(&self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            TextEncoding::Ascii => {
+                text.chars().map(|chr| if chr.is_ascii() { Some(chr as u8) } else { None }).collect()
+            },
+
+            TextEncoding::Latin1 => {
+                text.chars().map(|chr| if (chr as u32) <= 0xFF { Some(chr as u8) } else { None }).collect()
+            },
+
+            TextEncoding::Utf8 => Some(text.as_bytes().to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextEncoding::Ascii => write!(f, "ascii"),
+            TextEncoding::Latin1 => write!(f, "latin1"),
+            TextEncoding::Utf8 => write!(f, "utf8"),
+        }
     }
 }
 
 /// A value is a primitive binary object.
-/// These can be 8/16/32/64 bit signed/unsigned integers,
-/// of single/double precision floats.
-#[derive(PartialEq, Debug, Copy, Clone, Deserialize)]
+/// These can be 8/16/32/64/128 bit signed/unsigned integers,
+/// of half/bfloat16/single/double precision floats, or strings.
+#[derive(PartialEq, Debug, Clone, Deserialize)]
 pub enum Value {
     Uint8(u8),
     Int8(i8),
@@ -333,8 +872,15 @@ pub enum Value {
     Int32(i32),
     Uint64(u64),
     Int64(i64),
+    Uint128(u128),
+    Int128(i128),
     Float(f32),
     Double(f64),
+    Half(f32),
+    BFloat16(f32),
+    Bool(bool),
+    Str(String),
+    Time(Timestamp),
 }
 
 impl Value {
@@ -348,8 +894,45 @@ impl Value {
             Value::Int32(val)  => format!("{}", val),
             Value::Uint64(val) => format!("{}", val),
             Value::Int64(val)  => format!("{}", val),
+            Value::Uint128(val) => format!("{}", val),
+            Value::Int128(val) => format!("{}", val),
             Value::Float(val)  => format!("{}", val),
             Value::Double(val) => format!("{}", val),
+            Value::Half(val)   => format!("{}", val),
+            Value::BFloat16(val) => format!("{}", val),
+            Value::Bool(val)   => format!("{}", val),
+            Value::Str(val)    => val.clone(),
+            Value::Time(val)   => val.to_string(),
+        }
+    }
+
+    /// The value as a signed integer, if it is an integer that fits in an i128.
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Uint8(val)  => Some(*val as i128),
+            Value::Int8(val)   => Some(*val as i128),
+            Value::Uint16(val) => Some(*val as i128),
+            Value::Int16(val)  => Some(*val as i128),
+            Value::Uint32(val) => Some(*val as i128),
+            Value::Int32(val)  => Some(*val as i128),
+            Value::Uint64(val) => Some(*val as i128),
+            Value::Int64(val)  => Some(*val as i128),
+            Value::Uint128(val) => i128::try_from(*val).ok(),
+            Value::Int128(val) => Some(*val),
+            _ => None,
+        }
+    }
+
+    /// The value as a double, if it is a number.
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Uint128(val) => Some(*val as f64),
+            Value::Int128(val) => Some(*val as f64),
+            Value::Float(val)  => Some(*val as f64),
+            Value::Double(val) => Some(*val),
+            Value::Half(val)   => Some(*val as f64),
+            Value::BFloat16(val) => Some(*val as f64),
+            _ => self.as_integer().map(|val| val as f64),
         }
     }
 }
@@ -365,9 +948,73 @@ impl HasNumBits for Value {
             Value::Int32(val)  => 32,
             Value::Uint64(val) => 64,
             Value::Int64(val)  => 64,
+            Value::Uint128(_) => 128,
+            Value::Int128(_)  => 128,
             Value::Float(val)  => 32,
             Value::Double(val) => 64,
+            Value::Half(_)     => 16,
+            Value::BFloat16(_) => 16,
+            Value::Bool(_)     => 1,
+            Value::Str(val)    => val.len() * 8,
+            Value::Time(_)     => 128,
+        }
+    }
+}
+
+/// Which values are written out for fields that are converted, such as
+/// enumerations and calibrated fields. Converted values are used by default.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub enum ValuesMode {
+    Raw,
+    #[default]
+    Converted,
+    Both,
+}
+
+impl FromStr for ValuesMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ValuesMode, String> {
+        match s.trim().to_lowercase().as_str() {
+            "raw" => Ok(ValuesMode::Raw),
+            "converted" => Ok(ValuesMode::Converted),
+            "both" => Ok(ValuesMode::Both),
+            _ => Err(format!("'{}' should be one of raw, converted, or both", s)),
+        }
+    }
+}
+
+/// A pattern of bytes marking the start of each record, such as the CCSDS
+/// attached sync marker 0x1ACFFC1D, given in hex with an optional '0x' prefix.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SyncMarker(pub Vec<u8>);
+
+impl FromStr for SyncMarker {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SyncMarker, String> {
+        let trimmed = s.trim();
+        let digits = trimmed.strip_prefix("0x").or_else(|| trimmed.strip_prefix("0X")).unwrap_or(trimmed);
+
+        if digits.is_empty() || digits.len() % 2 == 1 || !digits.chars().all(|chr| chr.is_ascii_hexdigit()) {
+            return Err(format!("'{}' should be an even number of hex digits, such as 0x1ACFFC1D", s));
         }
+
+        let bytes = (0..digits.len()).step_by(2)
+                                     .map(|index| u8::from_str_radix(&digits[index..index + 2], 16).unwrap())
+                                     .collect();
+
+        Ok(SyncMarker(bytes))
+    }
+}
+
+/// Quote a csv entry if it contains characters that would otherwise
+/// break up the record, such as commas in a decoded string.
+pub fn csv_quote(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
     }
 }
 
@@ -405,7 +1052,7 @@ pub struct Field {
 
 impl Field {
     pub fn to_record(&self) -> String {
-        format!("{},{},{}", self.typ.to_string(), self.description, self.value.to_string())
+        format!("{},{},{}", self.typ.to_string(), csv_quote(&self.description), csv_quote(&self.value.to_string()))
     }
 
     pub fn u8(val: u8, endianness: Endianness, descr: String) -> Field {
@@ -447,6 +1094,10 @@ impl Field {
     pub fn double(val: f64, endianness: Endianness, descr: String) -> Field {
         Field { value: Value::Double(val), typ: FieldType::double(endianness), description: descr }
     }
+
+    pub fn string(val: &str, num_bytes: NumBytes, descr: String) -> Field {
+        Field { value: Value::Str(val.to_string()), typ: FieldType::string(num_bytes), description: descr }
+    }
 }
 
 impl fmt::Display for Field {
@@ -461,3 +1112,163 @@ impl HasNumBits for Field {
     }
 }
 
+
+#[test]
+fn test_field_type_string() {
+    assert_eq!("char".parse(), Ok(FieldType::string(1)));
+    assert_eq!("string16".parse(), Ok(FieldType::string(16)));
+    assert_eq!("string8_space_latin1".parse(), Ok(FieldType::Str(8, Padding::Space, TextEncoding::Latin1)));
+    assert_eq!("string8_utf8".parse(), Ok(FieldType::Str(8, Padding::Nul, TextEncoding::Utf8)));
+    assert!("string".parse::<FieldType>().is_err());
+    assert!("string0".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Str(8, Padding::Space, TextEncoding::Utf8).to_string(), "string8_space_utf8");
+    assert_eq!(FieldType::string(4).to_string(), "string4");
+}
+
+#[test]
+fn test_field_type_pad() {
+    assert_eq!("pad".parse(), Ok(FieldType::Pad(8, Endianness::Big, BitSize(8))));
+    assert_eq!("reserved16".parse(), Ok(FieldType::Pad(16, Endianness::Big, BitSize(16))));
+    assert_eq!("pad3_le:16".parse(), Ok(FieldType::Pad(3, Endianness::Little, BitSize(16))));
+    assert!("pad0".parse::<FieldType>().is_err());
+    assert!("pad129".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Pad(3, Endianness::Little, BitSize(16)).to_string(), "pad3_le:16");
+}
+
+#[test]
+fn test_padding_trim() {
+    assert_eq!(Padding::Nul.trim(b"abc\0\0\0"), b"abc");
+    assert_eq!(Padding::Nul.trim(b"abc\0def"), b"abc");
+    assert_eq!(Padding::Space.trim(b"a c   "), b"a c");
+    assert_eq!(Padding::Space.trim(b"    "), b"");
+}
+
+#[test]
+fn test_field_type_small_floats() {
+    assert_eq!("half_be".parse(), Ok(FieldType::half(Endianness::Big)));
+    assert_eq!("bfloat16_le".parse(), Ok(FieldType::bfloat16(Endianness::Little)));
+    assert_eq!("float_le".parse(), Ok(FieldType::float(Endianness::Little)));
+    assert_eq!("float32_le".parse(), Ok(FieldType::float(Endianness::Little)));
+    assert!("float16_be".parse::<FieldType>().is_err());
+    assert!("bfloat_be".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::bfloat16(Endianness::Big).to_string(), "bfloat16_be");
+}
+
+#[test]
+fn test_field_type_varint() {
+    assert_eq!("uleb128".parse(), Ok(FieldType::Varint(VarintEncoding::ULeb128)));
+    assert_eq!("varint".parse(), Ok(FieldType::Varint(VarintEncoding::ULeb128)));
+    assert_eq!(" SLEB128".parse(), Ok(FieldType::Varint(VarintEncoding::SLeb128)));
+    assert_eq!("zigzag".parse(), Ok(FieldType::Varint(VarintEncoding::ZigZag)));
+    assert!("uleb128_be".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Varint(VarintEncoding::ZigZag).to_string(), "zigzag");
+    assert!(FieldType::Varint(VarintEncoding::ULeb128).is_variable());
+    assert!(!FieldType::u8(Endianness::Big).is_variable());
+}
+
+#[test]
+fn test_field_type_blob() {
+    assert_eq!("hex".parse(), Ok(FieldType::Blob(BlobFormat::Hex)));
+    assert_eq!("Base64".parse(), Ok(FieldType::Blob(BlobFormat::Base64)));
+    assert_eq!("text".parse(), Ok(FieldType::Blob(BlobFormat::Text(TextEncoding::Ascii))));
+    assert_eq!("text_utf8".parse(), Ok(FieldType::Blob(BlobFormat::Text(TextEncoding::Utf8))));
+    assert!("hex_utf8".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Blob(BlobFormat::Text(TextEncoding::Ascii)).to_string(), "text");
+    assert_eq!(FieldType::Blob(BlobFormat::Base64).to_string(), "base64");
+    assert!(FieldType::Blob(BlobFormat::Hex).is_variable());
+}
+
+#[test]
+fn test_field_type_checksum() {
+    assert_eq!("crc16_ccitt_be".parse(), Ok(FieldType::Checksum(ChecksumKind::Crc16Ccitt, Endianness::Big)));
+    assert_eq!("crc32_le".parse(), Ok(FieldType::Checksum(ChecksumKind::Crc32, Endianness::Little)));
+    assert_eq!("fletcher16".parse(), Ok(FieldType::Checksum(ChecksumKind::Fletcher16, Endianness::Big)));
+    assert_eq!("sum8".parse(), Ok(FieldType::Checksum(ChecksumKind::Sum8, Endianness::Big)));
+    assert_eq!("XOR8".parse(), Ok(FieldType::Checksum(ChecksumKind::Xor8, Endianness::Big)));
+    assert!("crc16".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Checksum(ChecksumKind::Sum16, Endianness::Little).to_string(), "sum16_le");
+    assert_eq!(FieldType::Checksum(ChecksumKind::Xor8, Endianness::Little).to_string(), "xor8");
+    assert_eq!(FieldType::Checksum(ChecksumKind::Crc32, Endianness::Big).num_bits(), 32);
+    assert!(FieldType::Checksum(ChecksumKind::Crc32, Endianness::Big).is_checksum());
+}
+
+#[test]
+fn test_field_type_bcd() {
+    assert_eq!("bcd16_be".parse(), Ok(FieldType::Bcd(16, Endianness::Big, BitSize(16), BcdPacking::Packed)));
+    assert_eq!("bcd_le".parse(), Ok(FieldType::Bcd(8, Endianness::Little, BitSize(8), BcdPacking::Packed)));
+    assert_eq!("bcd4_be:8".parse(), Ok(FieldType::Bcd(4, Endianness::Big, BitSize(8), BcdPacking::Packed)));
+    assert_eq!("ubcd16_be".parse(), Ok(FieldType::Bcd(16, Endianness::Big, BitSize(16), BcdPacking::Unpacked)));
+    assert!("bcd6_be:8".parse::<FieldType>().is_err());
+    assert!("ubcd4_be:8".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Bcd(4, Endianness::Big, BitSize(8), BcdPacking::Packed).to_string(), "bcd4_be:8");
+    assert_eq!(FieldType::Bcd(16, Endianness::Little, BitSize(16), BcdPacking::Unpacked).to_string(), "ubcd16_le");
+}
+
+#[test]
+fn test_field_type_time() {
+    assert_eq!("cuc4.2".parse(), Ok(FieldType::Time(TimeCode::Cuc(4, 2), CCSDS_EPOCH)));
+    assert_eq!("CDS2".parse(), Ok(FieldType::Time(TimeCode::Cds(2, 0), CCSDS_EPOCH)));
+    assert_eq!("cds3.4@1970-01-01".parse(), Ok(FieldType::Time(TimeCode::Cds(3, 4), Epoch(0))));
+    assert_eq!("cuc4.0@2000-01-01T11:58:55Z".parse(), Ok(FieldType::Time(TimeCode::Cuc(4, 0), Epoch(946727935))));
+    assert!("cuc5.0".parse::<FieldType>().is_err());
+    assert!("cds2.1".parse::<FieldType>().is_err());
+    assert!("cuc4.2@yesterday".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Time(TimeCode::Cuc(4, 2), CCSDS_EPOCH).to_string(), "cuc4.2");
+    assert_eq!(FieldType::Time(TimeCode::Cds(2, 2), Epoch(0)).to_string(), "cds2.2@1970-01-01");
+    assert_eq!(FieldType::Time(TimeCode::Cuc(4, 2), CCSDS_EPOCH).num_bits(), 48);
+}
+
+#[test]
+fn test_field_type_bool() {
+    assert_eq!("bool_be".parse(), Ok(FieldType::bool(Endianness::Big)));
+    assert_eq!("flag1_be:16".parse(), Ok(FieldType::Bool(1, Endianness::Big, BitSize(16))));
+    assert_eq!("bool3_le:8".parse(), Ok(FieldType::Bool(3, Endianness::Little, BitSize(8))));
+    assert!("bool0_be".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Bool(1, Endianness::Big, BitSize(16)).to_string(), "bool1_be:16");
+}
+
+#[test]
+fn test_field_type_bit_sizes() {
+    let endianness = Endianness::Big;
+
+    assert_eq!("uint12_be:24".parse(), Ok(FieldType::Uint(12, endianness, BitSize(24))));
+    assert_eq!("uint24_le".parse(), Ok(FieldType::Uint(24, Endianness::Little, BitSize(24))));
+    assert_eq!("int48_be".parse(), Ok(FieldType::Int(48, endianness, BitSize(48))));
+    assert_eq!("uint3_be:56".parse(), Ok(FieldType::Uint(3, endianness, BitSize(56))));
+    assert_eq!("uint12_be".parse(), Ok(FieldType::Uint(12, endianness, BitSize(16))));
+
+    assert!("uint12_be:12".parse::<FieldType>().is_err());
+    assert!("uint12_be:8".parse::<FieldType>().is_err());
+    assert!("uint8_be:72".parse::<FieldType>().is_ok());
+    assert!("uint8_be:136".parse::<FieldType>().is_err());
+    assert!("uint8_be:0".parse::<FieldType>().is_err());
+    assert!("uint129_be".parse::<FieldType>().is_err());
+
+    assert_eq!("uint128_le".parse(), Ok(FieldType::u128(Endianness::Little)));
+    assert_eq!("int128_be".parse(), Ok(FieldType::i128(endianness)));
+    assert_eq!("uint32_be:96".parse(), Ok(FieldType::Uint(32, endianness, BitSize(96))));
+    assert!("uint0_be".parse::<FieldType>().is_err());
+
+    assert_eq!(FieldType::Int(48, endianness, BitSize(48)).to_string(), "int48_be");
+    assert_eq!(FieldType::Uint(12, endianness, BitSize(24)).to_string(), "uint12_be:24");
+}
+
+#[test]
+fn test_sync_marker() {
+    assert_eq!("0x1ACFFC1D".parse(), Ok(SyncMarker(vec!(0x1A, 0xCF, 0xFC, 0x1D))));
+    assert_eq!(" eb90 ".parse(), Ok(SyncMarker(vec!(0xEB, 0x90))));
+
+    assert!("".parse::<SyncMarker>().is_err());
+    assert!("0x".parse::<SyncMarker>().is_err());
+    assert!("1ACFF".parse::<SyncMarker>().is_err());
+    assert!("1ACFFG".parse::<SyncMarker>().is_err());
+}
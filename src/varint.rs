@@ -0,0 +1,172 @@
+use std::fmt;
+use std::io::Read;
+
+
+/// The largest number of bytes in a varint, enough to hold 128 bits.
+const MAX_VARINT_BYTES: usize = 19;
+
+/// The encoding of a variable length integer. Each byte holds 7 bits of the
+/// value, least significant first, with the high bit set on all but the last byte.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Deserialize)]
+pub enum VarintEncoding {
+    /// Unsigned LEB128, also used for protobuf varints
+    ULeb128,
+
+    /// Signed LEB128, with the value sign extended from its last byte
+    SLeb128,
+
+    /// Signed values zigzag encoded into an unsigned LEB128, as in protobuf's sint types
+    ZigZag,
+}
+
+impl fmt::Display for VarintEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VarintEncoding::ULeb128 => write!(f, "uleb128"),
+            VarintEncoding::SLeb128 => write!(f, "sleb128"),
+            VarintEncoding::ZigZag => write!(f, "zigzag"),
+        }
+    }
+}
+
+/// Read the raw bits of a varint, returning them along with the number of bits read.
+/// Signed varints may extend their sign past the 128th bit.
+fn read_bits<R: Read>(reader: &mut R, signed: bool) -> Option<(u128, u32)> {
+    let mut bits: u128 = 0;
+    let mut byte = [0; 1];
+
+    for index in 0..MAX_VARINT_BYTES {
+        reader.read_exact(&mut byte).ok()?;
+
+        let shift = 7 * index as u32;
+        let payload = (byte[0] & 0x7F) as u128;
+
+        // bits beyond the 128th must be zero, or copies of the sign bit
+        if shift > 121 {
+            let excess = payload >> (128 - shift);
+            let sign_extension = if signed && (payload >> (127 - shift)) & 1 == 1 { 0x7F >> (128 - shift) } else { 0 };
+
+            if excess != sign_extension {
+                error!("Varint does not fit in 128 bits");
+                return None;
+            }
+        }
+
+        bits |= payload << shift;
+
+        if byte[0] & 0x80 == 0 {
+            return Some((bits, shift + 7));
+        }
+    }
+
+    error!("Varint is longer than {} bytes", MAX_VARINT_BYTES);
+    None
+}
+
+pub fn read_unsigned<R: Read>(reader: &mut R) -> Option<u128> {
+    read_bits(reader, false).map(|(bits, _)| bits)
+}
+
+pub fn read_signed<R: Read>(reader: &mut R) -> Option<i128> {
+    let (bits, num_bits) = read_bits(reader, true)?;
+
+    // sign extend from the last bit read
+    if num_bits < 128 && (bits >> (num_bits - 1)) & 1 == 1 {
+        Some((bits | (!0u128 << num_bits)) as i128)
+    } else {
+        Some(bits as i128)
+    }
+}
+
+pub fn read_zigzag<R: Read>(reader: &mut R) -> Option<i128> {
+    read_unsigned(reader).map(zigzag_decode)
+}
+
+pub fn write_unsigned(mut value: u128) -> Vec<u8> {
+    let mut bytes = vec!();
+
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+pub fn write_signed(mut value: i128) -> Vec<u8> {
+    let mut bytes = vec!();
+
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        // stop once the remaining bits are all copies of the sign bit
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return bytes;
+        }
+
+        bytes.push(byte | 0x80);
+    }
+}
+
+pub fn write_zigzag(value: i128) -> Vec<u8> {
+    write_unsigned(zigzag_encode(value))
+}
+
+fn zigzag_encode(value: i128) -> u128 {
+    ((value << 1) ^ (value >> 127)) as u128
+}
+
+fn zigzag_decode(bits: u128) -> i128 {
+    ((bits >> 1) as i128) ^ -((bits & 1) as i128)
+}
+
+#[test]
+fn test_varint_unsigned() {
+    assert_eq!(write_unsigned(0), vec!(0x00));
+    assert_eq!(write_unsigned(127), vec!(0x7F));
+    assert_eq!(write_unsigned(300), vec!(0xAC, 0x02));
+    assert_eq!(write_unsigned(624485), vec!(0xE5, 0x8E, 0x26));
+    assert_eq!(write_unsigned(u128::MAX).len(), 19);
+
+    assert_eq!(read_unsigned(&mut &[0xE5, 0x8E, 0x26][..]), Some(624485));
+    assert_eq!(read_unsigned(&mut &write_unsigned(u128::MAX)[..]), Some(u128::MAX));
+
+    // truncated varints and varints too large for 128 bits
+    assert_eq!(read_unsigned(&mut &[0xE5, 0x8E][..]), None);
+    let mut too_large = write_unsigned(u128::MAX);
+    too_large[18] = 0x7F;
+    assert_eq!(read_unsigned(&mut &too_large[..]), None);
+}
+
+#[test]
+fn test_varint_signed() {
+    assert_eq!(write_signed(0), vec!(0x00));
+    assert_eq!(write_signed(-1), vec!(0x7F));
+    assert_eq!(write_signed(63), vec!(0x3F));
+    assert_eq!(write_signed(64), vec!(0xC0, 0x00));
+    assert_eq!(write_signed(-123456), vec!(0xC0, 0xBB, 0x78));
+
+    assert_eq!(read_signed(&mut &[0xC0, 0xBB, 0x78][..]), Some(-123456));
+    assert_eq!(read_signed(&mut &[0xC0, 0x00][..]), Some(64));
+    assert_eq!(read_signed(&mut &write_signed(i128::MIN)[..]), Some(i128::MIN));
+    assert_eq!(read_signed(&mut &write_signed(i128::MAX)[..]), Some(i128::MAX));
+}
+
+#[test]
+fn test_varint_zigzag() {
+    assert_eq!(write_zigzag(0), vec!(0x00));
+    assert_eq!(write_zigzag(-1), vec!(0x01));
+    assert_eq!(write_zigzag(1), vec!(0x02));
+    assert_eq!(write_zigzag(-64), vec!(0x7F));
+    assert_eq!(write_zigzag(64), vec!(0x80, 0x01));
+
+    assert_eq!(read_zigzag(&mut &[0x80, 0x01][..]), Some(64));
+    assert_eq!(read_zigzag(&mut &write_zigzag(i128::MIN)[..]), Some(i128::MIN));
+}